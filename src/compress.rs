@@ -0,0 +1,324 @@
+use std::io::{self, BufReader, ErrorKind, Read, Seek, SeekFrom};
+
+use bzip2::read::MultiBzDecoder;
+use flate2::read::MultiGzDecoder;
+
+/// Where [`Fits::open_with`](crate::Fits::open_with) decompresses a
+/// compressed FITS file, so that it can be read in random order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Decompress {
+    /// Decompress the whole file in memory.
+    #[default]
+    InMemory,
+    /// Decompress to an anonymous temporary file, removed when the
+    /// [`Fits`](crate::Fits) is dropped. Use it for files too big to fit in
+    /// memory.
+    TempFile,
+}
+
+/// Compression format of a file, detected from its magic bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Compression {
+    Gzip,
+    Bzip2,
+    /// LZW compression of the Unix `compress` program (`.Z` files).
+    Lzw,
+}
+
+const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b];
+const BZIP2_MAGIC: &[u8] = b"BZh";
+const LZW_MAGIC: &[u8] = &[0x1f, 0x9d];
+
+impl Compression {
+    /// Detect the compression of `reader` from its first bytes, then rewind
+    /// it. Return [`None`] if `reader` is not compressed.
+    pub(crate) fn detect<R: Read + Seek>(reader: &mut R) -> io::Result<Option<Compression>> {
+        let mut magic = Vec::with_capacity(3);
+        reader.by_ref().take(3).read_to_end(&mut magic)?;
+        reader.seek(SeekFrom::Start(0))?;
+        Ok(if magic.starts_with(GZIP_MAGIC) {
+            Some(Compression::Gzip)
+        } else if magic.starts_with(BZIP2_MAGIC) {
+            Some(Compression::Bzip2)
+        } else if magic.starts_with(LZW_MAGIC) {
+            Some(Compression::Lzw)
+        } else {
+            None
+        })
+    }
+
+    /// Wrap `reader` into a reader of its decompressed content.
+    pub(crate) fn decoder<'a, R: Read + 'a>(self, reader: R) -> io::Result<Box<dyn Read + 'a>> {
+        Ok(match self {
+            Compression::Gzip => Box::new(MultiGzDecoder::new(reader)),
+            Compression::Bzip2 => Box::new(MultiBzDecoder::new(reader)),
+            Compression::Lzw => Box::new(LzwDecoder::new(reader)?),
+        })
+    }
+}
+
+const LZW_BLOCK_MODE: u8 = 0x80;
+const LZW_MAX_BITS_MASK: u8 = 0x1f;
+const LZW_INIT_BITS: u32 = 9;
+const LZW_CLEAR: u32 = 256;
+
+/// Decoder of the LZW format written by `compress`.
+///
+/// Codes are packed LSB first, by groups of 8 codes. When the code width
+/// changes, `compress` pads the current group to its full size, so the
+/// padding bits are skipped the same way.
+struct LzwDecoder<R> {
+    reader: BufReader<R>,
+    max_bits: u32,
+    block_mode: bool,
+    /// Current code width.
+    n_bits: u32,
+    /// Next free entry in the table.
+    free_ent: u32,
+    prefix: Vec<u16>,
+    suffix: Vec<u8>,
+    old_code: Option<u32>,
+    fin_char: u8,
+    bit_buf: u32,
+    bit_count: u32,
+    /// Number of bits read since the header.
+    bit_pos: u64,
+    /// Value of `bit_pos` when the code width last changed.
+    group_start: u64,
+    /// Decoded bytes of the last code, in reverse order.
+    stack: Vec<u8>,
+    end: bool,
+}
+
+impl<R: Read> LzwDecoder<R> {
+    fn new(reader: R) -> io::Result<Self> {
+        let mut reader = BufReader::new(reader);
+        let mut header = [0u8; 3];
+        reader.read_exact(&mut header)?;
+        let max_bits = (header[2] & LZW_MAX_BITS_MASK) as u32;
+        if &header[..2] != LZW_MAGIC || !(LZW_INIT_BITS..=16).contains(&max_bits) {
+            return Err(invalid_lzw("Invalid header"));
+        }
+        let block_mode = header[2] & LZW_BLOCK_MODE != 0;
+        Ok(LzwDecoder {
+            reader,
+            max_bits,
+            block_mode,
+            n_bits: LZW_INIT_BITS,
+            free_ent: if block_mode { LZW_CLEAR + 1 } else { LZW_CLEAR },
+            prefix: vec![0; 1 << max_bits],
+            suffix: (0..1u32 << max_bits).map(|code| code as u8).collect(),
+            old_code: None,
+            fin_char: 0,
+            bit_buf: 0,
+            bit_count: 0,
+            bit_pos: 0,
+            group_start: 0,
+            stack: Vec::new(),
+            end: false,
+        })
+    }
+
+    /// Read `n` bits. Return [`None`] at the end of the input.
+    fn read_bits(&mut self, n: u32) -> io::Result<Option<u32>> {
+        while self.bit_count < n {
+            let mut byte = [0u8];
+            match self.reader.read_exact(&mut byte) {
+                Ok(()) => {}
+                Err(ref e) if e.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+                Err(e) => return Err(e),
+            }
+            self.bit_buf |= (byte[0] as u32) << self.bit_count;
+            self.bit_count += 8;
+        }
+        let bits = self.bit_buf & ((1 << n) - 1);
+        self.bit_buf >>= n;
+        self.bit_count -= n;
+        self.bit_pos += n as u64;
+        Ok(Some(bits))
+    }
+
+    /// Skip the padding up to the end of the current group of codes. Return
+    /// `false` at the end of the input.
+    fn skip_group_padding(&mut self) -> io::Result<bool> {
+        let group_bits = self.n_bits as u64 * 8;
+        let used = (self.bit_pos - self.group_start) % group_bits;
+        let mut padding = (group_bits - used) % group_bits;
+        while padding > 0 {
+            let n = padding.min(8);
+            if self.read_bits(n as u32)?.is_none() {
+                return Ok(false);
+            }
+            padding -= n;
+        }
+        self.group_start = self.bit_pos;
+        Ok(true)
+    }
+
+    /// Decode the next code into `stack`. Return `false` at the end of the
+    /// input.
+    fn decode_next(&mut self) -> io::Result<bool> {
+        let max_max_code = 1 << self.max_bits;
+        loop {
+            let max_code = if self.n_bits == self.max_bits {
+                max_max_code
+            } else {
+                (1 << self.n_bits) - 1
+            };
+            if self.free_ent > max_code {
+                if !self.skip_group_padding()? {
+                    return Ok(false);
+                }
+                self.n_bits += 1;
+                continue;
+            }
+            let code = match self.read_bits(self.n_bits)? {
+                Some(code) => code,
+                None => return Ok(false),
+            };
+            let old_code = match self.old_code {
+                Some(old_code) => old_code,
+                None => {
+                    if code >= LZW_CLEAR {
+                        return Err(invalid_lzw("Invalid first code"));
+                    }
+                    self.old_code = Some(code);
+                    self.fin_char = code as u8;
+                    self.stack.push(self.fin_char);
+                    return Ok(true);
+                }
+            };
+            if code == LZW_CLEAR && self.block_mode {
+                self.free_ent = LZW_CLEAR;
+                if !self.skip_group_padding()? {
+                    return Ok(false);
+                }
+                self.n_bits = LZW_INIT_BITS;
+                continue;
+            }
+            let mut entry = code;
+            if entry > self.free_ent {
+                return Err(invalid_lzw("Invalid code"));
+            } else if entry == self.free_ent {
+                self.stack.push(self.fin_char);
+                entry = old_code;
+            }
+            while entry >= LZW_CLEAR {
+                self.stack.push(self.suffix[entry as usize]);
+                entry = self.prefix[entry as usize] as u32;
+            }
+            self.fin_char = entry as u8;
+            self.stack.push(self.fin_char);
+            if self.free_ent < max_max_code {
+                self.prefix[self.free_ent as usize] = old_code as u16;
+                self.suffix[self.free_ent as usize] = self.fin_char;
+                self.free_ent += 1;
+            }
+            self.old_code = Some(code);
+            return Ok(true);
+        }
+    }
+}
+
+impl<R: Read> Read for LzwDecoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.stack.is_empty() && !self.end && !self.decode_next()? {
+            self.end = true;
+        }
+        let mut count = 0;
+        while count < buf.len() {
+            match self.stack.pop() {
+                Some(byte) => buf[count] = byte,
+                None => break,
+            }
+            count += 1;
+        }
+        Ok(count)
+    }
+}
+
+fn invalid_lzw(msg: &str) -> io::Error {
+    io::Error::new(
+        ErrorKind::InvalidData,
+        format!("Corrupt LZW compressed data: {}", msg),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Compression, LzwDecoder};
+    use std::fs::File;
+    use std::io::{Cursor, ErrorKind, Read};
+
+    fn read_all(path: &str) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        File::open(path).unwrap().read_to_end(&mut bytes).unwrap();
+        bytes
+    }
+
+    #[test]
+    fn detect_compression() {
+        let detect = |path| Compression::detect(&mut File::open(path).unwrap()).unwrap();
+        assert_eq!(detect("test/testprog.fit"), None);
+        assert_eq!(detect("test/testprog.fit.gz"), Some(Compression::Gzip));
+        assert_eq!(detect("test/testprog.fit.bz2"), Some(Compression::Bzip2));
+        assert_eq!(detect("test/testprog.fit.Z"), Some(Compression::Lzw));
+        assert_eq!(
+            Compression::detect(&mut Cursor::new(b"\x1f")).unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn decompress_all_formats() {
+        let expected = read_all("test/testprog.fit");
+        for path in &[
+            "test/testprog.fit.gz",
+            "test/testprog.fit.bz2",
+            "test/testprog.fit.Z",
+        ] {
+            let mut file = File::open(path).unwrap();
+            let compression = Compression::detect(&mut file).unwrap().unwrap();
+            let mut decompressed = Vec::new();
+            compression
+                .decoder(file)
+                .unwrap()
+                .read_to_end(&mut decompressed)
+                .unwrap();
+            assert!(decompressed == expected, "{} differs", path);
+        }
+    }
+
+    #[test]
+    fn lzw_without_block_mode() {
+        // "ABABABA" with 9-bit codes: 'A', 'B', 256 ("AB"), 258 ("ABA")
+        let codes = [0x41u32, 0x42, 256, 258];
+        let mut bytes = vec![0x1f, 0x9d, 16];
+        let mut bits = 0u64;
+        for (i, code) in codes.iter().enumerate() {
+            bits |= (*code as u64) << (9 * i);
+        }
+        bytes.extend((0..5).map(|i| (bits >> (8 * i)) as u8));
+        let mut decoded = Vec::new();
+        LzwDecoder::new(Cursor::new(bytes))
+            .unwrap()
+            .read_to_end(&mut decoded)
+            .unwrap();
+        assert_eq!(decoded, b"ABABABA");
+    }
+
+    #[test]
+    fn lzw_corrupt_data() {
+        let header_error = LzwDecoder::new(Cursor::new(vec![0x1f, 0x9d, 0x80 | 20])).err();
+        assert_eq!(header_error.unwrap().kind(), ErrorKind::InvalidData);
+        // Code 300 is not yet in the table
+        let bytes = vec![0x1f, 0x9d, 0x90, 0x41, 0x58, 0x02];
+        let mut decoded = Vec::new();
+        let error = LzwDecoder::new(Cursor::new(bytes))
+            .unwrap()
+            .read_to_end(&mut decoded)
+            .unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+        assert_eq!(decoded, b"A");
+    }
+}
@@ -0,0 +1,167 @@
+use std::error;
+use std::fmt;
+use std::io;
+
+use fits::HeaderValue;
+
+/// Error raised when reading a malformed or unsupported FITS file.
+///
+/// Returned by the fallible `try_*` methods of [`Fits`](crate::Fits) and
+/// [`Hdu`](crate::Hdu).
+#[derive(Debug)]
+pub enum FitsError {
+    /// I/O error. `offset` is the position in file where it happened, if
+    /// known.
+    Io {
+        offset: Option<u64>,
+        error: io::Error,
+    },
+    /// The header of HDU number `hdu` starting at `offset` ends before its
+    /// `END` keyword.
+    TruncatedHeader { hdu: usize, offset: u64 },
+    /// A keyword required to read the HDU is missing. `hdu` is [`None`] if
+    /// the HDU is not read from a file.
+    MissingKeyword { hdu: Option<usize>, keyword: String },
+    /// A keyword has a value of unexpected type, or out of the range allowed
+    /// by the standard. `hdu` is [`None`] if the HDU is not read from a file.
+    InvalidKeyword { hdu: Option<usize>, keyword: String },
+    /// A keyword to write is neither a standard keyword nor a keyword
+    /// starting with `HIERARCH `. `hdu` is [`None`] if the HDU is not read
+    /// from a file.
+    InvalidKeywordName { hdu: Option<usize>, keyword: String },
+    /// A string value or a comment to write in the card of `keyword` has
+    /// other characters than printable ASCII. `hdu` is [`None`] if the HDU
+    /// is not read from a file.
+    InvalidText { hdu: Option<usize>, keyword: String },
+    /// A keyword has a value that cannot be converted to the requested type.
+    /// `hdu` is [`None`] if the HDU is not read from a file.
+    UnexpectedType {
+        hdu: Option<usize>,
+        keyword: String,
+        expected: &'static str,
+        value: HeaderValue,
+    },
+    /// `BITPIX` is not one of 8, 16, 32, 64, -32 or -64.
+    UnsupportedBitpix { hdu: Option<usize>, bitpix: i64 },
+    /// A data array to write does not match the `BITPIX` and `NAXISn`
+    /// keywords of its HDU.
+    DataMismatch { hdu: Option<usize> },
+    /// The data array of an HDU holding a table or random groups cannot be
+    /// edited, as it is not the whole data unit.
+    NotAnImage { hdu: Option<usize> },
+    /// Integer data with undefined values uses every value of its type, so
+    /// that none is left for `BLANK`.
+    NoFreeBlank,
+    /// A section does not fit in the data array.
+    InvalidSection(String),
+    /// The variable length array descriptor in `row` of binary table
+    /// `column` is negative or points outside of the heap. Rows and columns
+    /// are counted from 0.
+    InvalidDescriptor {
+        hdu: Option<usize>,
+        column: usize,
+        row: usize,
+    },
+}
+
+impl fmt::Display for FitsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FitsError::Io {
+                offset: Some(offset),
+                error,
+            } => write!(f, "I/O error at offset {}: {}", offset, error),
+            FitsError::Io {
+                offset: None,
+                error,
+            } => write!(f, "I/O error: {}", error),
+            FitsError::TruncatedHeader { hdu, offset } => write!(
+                f,
+                "Header of HDU {} at offset {} ends before END keyword",
+                hdu, offset
+            ),
+            FitsError::MissingKeyword { hdu, keyword } => {
+                write!(f, "Missing keyword {}", keyword)?;
+                write_hdu_index(f, *hdu)
+            }
+            FitsError::InvalidKeyword { hdu, keyword } => {
+                write!(f, "Invalid value for keyword {}", keyword)?;
+                write_hdu_index(f, *hdu)
+            }
+            FitsError::InvalidKeywordName { hdu, keyword } => {
+                write!(f, "Invalid keyword name {:?}", keyword)?;
+                write_hdu_index(f, *hdu)
+            }
+            FitsError::InvalidText { hdu, keyword } => {
+                write!(f, "Text of keyword {} is not printable ASCII", keyword)?;
+                write_hdu_index(f, *hdu)
+            }
+            FitsError::UnexpectedType {
+                hdu,
+                keyword,
+                expected,
+                value,
+            } => {
+                write!(f, "Expected {} for keyword {}", expected, keyword)?;
+                write_hdu_index(f, *hdu)?;
+                write!(f, ", found {:?}", value)
+            }
+            FitsError::UnsupportedBitpix { hdu, bitpix } => {
+                write!(f, "Unexpected value for BITPIX: {}", bitpix)?;
+                write_hdu_index(f, *hdu)
+            }
+            FitsError::DataMismatch { hdu } => {
+                write!(f, "Data array does not match BITPIX and NAXISn keywords")?;
+                write_hdu_index(f, *hdu)
+            }
+            FitsError::NotAnImage { hdu } => {
+                write!(f, "Data array of a table or random groups cannot be edited")?;
+                write_hdu_index(f, *hdu)
+            }
+            FitsError::NoFreeBlank => write!(f, "No value left for BLANK in integer data"),
+            FitsError::InvalidSection(msg) => write!(f, "{}", msg),
+            FitsError::InvalidDescriptor { hdu, column, row } => {
+                write!(
+                    f,
+                    "Invalid variable length array descriptor in row {} of column {}",
+                    row, column
+                )?;
+                write_hdu_index(f, *hdu)
+            }
+        }
+    }
+}
+
+fn write_hdu_index(f: &mut fmt::Formatter, hdu: Option<usize>) -> fmt::Result {
+    match hdu {
+        Some(hdu) => write!(f, " in HDU {}", hdu),
+        None => Ok(()),
+    }
+}
+
+impl error::Error for FitsError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            FitsError::Io { error, .. } => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for FitsError {
+    fn from(error: io::Error) -> FitsError {
+        FitsError::Io {
+            offset: None,
+            error,
+        }
+    }
+}
+
+impl From<FitsError> for io::Error {
+    fn from(error: FitsError) -> io::Error {
+        match error {
+            FitsError::Io { error, .. } => error,
+            error => io::Error::new(io::ErrorKind::InvalidData, error),
+        }
+    }
+}
@@ -1,15 +1,73 @@
-use std::fs::File;
-use std::io::{Error, Read, Seek, SeekFrom};
+use std::convert::TryFrom;
+use std::fmt;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufReader, BufWriter, Cursor, Error, ErrorKind, Read, Seek, SeekFrom, Write};
 use std::ops::{Index, IndexMut};
 use std::path::Path;
 use std::result::Result;
+use std::slice;
 use std::str::{from_utf8, FromStr};
 use std::sync::atomic::{AtomicPtr, Ordering};
 use std::sync::{Arc, Mutex, MutexGuard, RwLock};
 
-use byteorder::{BigEndian, ReadBytesExt};
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
 
-type FileRc = Arc<Mutex<File>>;
+use compress::{Compression, Decompress};
+use error::FitsError;
+#[cfg(feature = "mmap")]
+use mmap::{DataView, MmapStorage, RawElement};
+use table::{AsciiTable, BinTable};
+
+type FileRc = Arc<Mutex<Box<dyn Storage>>>;
+
+/// Source a [`Fits`] is read from and written to.
+pub(crate) trait Storage: Read + Write + Seek + Send + fmt::Debug {
+    /// Memory map of the whole source, if it is mapped.
+    fn mapping(&self) -> Option<Arc<dyn Mapping>> {
+        None
+    }
+}
+
+/// Bytes of a file mapped in memory.
+pub(crate) trait Mapping: AsRef<[u8]> + Send + Sync + fmt::Debug {}
+
+impl Storage for File {}
+
+/// Read-only source of a [`Fits`] created with [`Fits::from_reader`].
+struct ReadOnly<R>(R);
+
+impl<R: Read + Seek + Send> Storage for ReadOnly<R> {}
+
+impl<R: Read> Read for ReadOnly<R> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+        self.0.read(buf)
+    }
+}
+
+impl<R: Seek> Seek for ReadOnly<R> {
+    fn seek(&mut self, pos: SeekFrom) -> Result<u64, Error> {
+        self.0.seek(pos)
+    }
+}
+
+impl<R> Write for ReadOnly<R> {
+    fn write(&mut self, _buf: &[u8]) -> Result<usize, Error> {
+        Err(Error::new(
+            ErrorKind::Unsupported,
+            "FITS read from a reader cannot be written",
+        ))
+    }
+
+    fn flush(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+impl<R> fmt::Debug for ReadOnly<R> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("ReadOnly")
+    }
+}
 
 /// Represent an open FITS file.
 ///
@@ -31,35 +89,74 @@ impl Drop for Fits {
 }
 
 /// An iterator over [`Hdu`]s. Obtained from a consumed [`Fits`] object.
+///
+/// Panic if an [`Hdu`] cannot be read.
 pub struct FitsIntoIter {
     fits: Fits,
     position: u64,
+    count: usize,
 }
 
 /// An iterator over references to [`Hdu`]s.
 ///
-/// Use caching to avoid rereading the same data from file.
+/// Use caching to avoid rereading the same data from file. Panic if an
+/// [`Hdu`] cannot be read, see [`FitsTryIter`] for a fallible version.
 pub struct FitsIter<'f> {
     fits: &'f Fits,
     position: u64,
     count: usize,
 }
 
+/// An iterator over references to [`Hdu`]s, returning an error if an
+/// [`Hdu`] cannot be read. Obtained with [`Fits::try_iter`].
+///
+/// Iteration stops after the first error.
+pub struct FitsTryIter<'f> {
+    iter: FitsIter<'f>,
+    failed: bool,
+}
+
 /// An iterator over mutable references to [`Hdu`]s.
 ///
-/// Use caching to avoid rereading the same data from file.
+/// Use caching to avoid rereading the same data from file. Panic if an
+/// [`Hdu`] cannot be read, see [`FitsTryIterMut`] for a fallible version.
 pub struct FitsIterMut<'f> {
     fits: &'f mut Fits,
     position: u64,
     count: usize,
 }
 
+/// An iterator over mutable references to [`Hdu`]s, returning an error if
+/// an [`Hdu`] cannot be read. Obtained with [`Fits::try_iter_mut`].
+///
+/// Iteration stops after the first error.
+pub struct FitsTryIterMut<'f> {
+    iter: FitsIterMut<'f>,
+    failed: bool,
+}
+
 /// Represent an HDU as defined in [FITS standard 4.1](https://archive.stsci.edu/fits/fits_standard/node13.html#SECTION00810000000000000000).
 #[derive(Debug)]
 pub struct Hdu {
     header: Vec<(HeaderKeyWord, Option<HeaderValueComment>)>,
+    /// Whether the header was edited since it was read from or written to
+    /// file.
+    header_changed: bool,
+    /// Header lines as last read from or written to file, with their card
+    /// images, so that the lines left unedited are written back unchanged.
+    original: Vec<OriginalLine>,
+    /// Whether the data was borrowed mutably since it was read from or
+    /// written to file.
+    data_changed: bool,
+    header_start: u64,
     data_start: u64,
-    file: FileRc,
+    /// Position of the [`Hdu`] in file, `0` for primary HDU. [`None`] if not
+    /// written to a file yet.
+    index: Option<usize>,
+    /// File the [`Hdu`] was read from. [`None`] if not written to a file yet.
+    file: Option<FileRc>,
+    /// Memory map of the file the [`Hdu`] was read from, if it is mapped.
+    mapping: Option<Arc<dyn Mapping>>,
     /// Cache of data inside Hdu
     data: RwLock<Option<FitsData>>,
 }
@@ -67,17 +164,24 @@ pub struct Hdu {
 /// Represent a data array inside an [`Hdu`].
 ///
 /// Follows data representation as defined in [FITS standard 6](https://archive.stsci.edu/fits/fits_standard/node42.html#SECTION001000000000000000000).
-#[derive(Debug)]
+///
+/// Integers stored with the unsigned convention (`BZERO = 2^(BITPIX - 1)`
+/// with `BITPIX` equal to 16, 32 or 64) are read as [`FitsData::IntegersU16`],
+/// [`FitsData::IntegersU32`] and [`FitsData::IntegersU64`].
+#[derive(Clone, Debug)]
 pub enum FitsData {
-    Characters(FitsDataArray<char>),
+    IntegersU8(FitsDataArray<Option<u8>>),
     IntegersI32(FitsDataArray<Option<i32>>),
+    IntegersU16(FitsDataArray<Option<u16>>),
     IntegersU32(FitsDataArray<Option<u32>>),
+    IntegersI64(FitsDataArray<Option<i64>>),
+    IntegersU64(FitsDataArray<Option<u64>>),
     FloatingPoint32(FitsDataArray<f32>),
     FloatingPoint64(FitsDataArray<f64>),
 }
 
 /// Actual array data inside the [`Hdu`]
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct FitsDataArray<T> {
     /// Shape of array.
     ///
@@ -88,18 +192,216 @@ pub struct FitsDataArray<T> {
     pub data: Vec<T>,
 }
 
+impl FitsData {
+    /// Shape of the array and number of elements.
+    fn shape_and_len(&self) -> (&[usize], usize) {
+        match *self {
+            FitsData::IntegersU8(ref array) => (&array.shape, array.data.len()),
+            FitsData::IntegersI32(ref array) => (&array.shape, array.data.len()),
+            FitsData::IntegersU16(ref array) => (&array.shape, array.data.len()),
+            FitsData::IntegersU32(ref array) => (&array.shape, array.data.len()),
+            FitsData::IntegersI64(ref array) => (&array.shape, array.data.len()),
+            FitsData::IntegersU64(ref array) => (&array.shape, array.data.len()),
+            FitsData::FloatingPoint32(ref array) => (&array.shape, array.data.len()),
+            FitsData::FloatingPoint64(ref array) => (&array.shape, array.data.len()),
+        }
+    }
+
+    /// Whether the array has undefined integer values.
+    fn has_undefined(&self) -> bool {
+        match *self {
+            FitsData::IntegersU8(ref array) => array.data.contains(&None),
+            FitsData::IntegersI32(ref array) => array.data.contains(&None),
+            FitsData::IntegersU16(ref array) => array.data.contains(&None),
+            FitsData::IntegersU32(ref array) => array.data.contains(&None),
+            FitsData::IntegersI64(ref array) => array.data.contains(&None),
+            FitsData::IntegersU64(ref array) => array.data.contains(&None),
+            FitsData::FloatingPoint32(_) | FitsData::FloatingPoint64(_) => false,
+        }
+    }
+
+    /// Section of provided `shape`, whose rows are made of `shape[0]`
+    /// elements taken every `step` from the offsets `rows`.
+    fn section(&self, shape: &[usize], rows: &[usize], step: usize) -> FitsData {
+        match *self {
+            FitsData::IntegersU8(ref array) => {
+                FitsData::IntegersU8(array.section(shape, rows, step))
+            }
+            FitsData::IntegersI32(ref array) => {
+                FitsData::IntegersI32(array.section(shape, rows, step))
+            }
+            FitsData::IntegersU16(ref array) => {
+                FitsData::IntegersU16(array.section(shape, rows, step))
+            }
+            FitsData::IntegersU32(ref array) => {
+                FitsData::IntegersU32(array.section(shape, rows, step))
+            }
+            FitsData::IntegersI64(ref array) => {
+                FitsData::IntegersI64(array.section(shape, rows, step))
+            }
+            FitsData::IntegersU64(ref array) => {
+                FitsData::IntegersU64(array.section(shape, rows, step))
+            }
+            FitsData::FloatingPoint32(ref array) => {
+                FitsData::FloatingPoint32(array.section(shape, rows, step))
+            }
+            FitsData::FloatingPoint64(ref array) => {
+                FitsData::FloatingPoint64(array.section(shape, rows, step))
+            }
+        }
+    }
+}
+
 impl<T> FitsDataArray<T> {
-    fn new(shape: &[usize], data: Vec<T>) -> Self {
+    /// Create a new data array with provided shape.
+    pub fn new(shape: &[usize], data: Vec<T>) -> Self {
         Self {
             shape: Vec::from(shape),
             data,
         }
     }
+
+    fn map<U, F: Fn(&T) -> U>(&self, f: F) -> FitsDataArray<U> {
+        FitsDataArray::new(&self.shape, self.data.iter().map(f).collect())
+    }
+
+    /// See [`FitsData::section`].
+    fn section(&self, shape: &[usize], rows: &[usize], step: usize) -> FitsDataArray<T>
+    where
+        T: Clone,
+    {
+        let data = rows
+            .iter()
+            .flat_map(|&row| self.data[row..].iter().step_by(step).take(shape[0]))
+            .cloned()
+            .collect();
+        FitsDataArray::new(shape, data)
+    }
+}
+
+impl FitsDataArray<Option<u8>> {
+    /// View bytes as text, each byte being a Latin-1 character.
+    ///
+    /// Undefined values are shown as the replacement character `U+FFFD`.
+    pub fn to_text(&self) -> String {
+        self.data
+            .iter()
+            .map(|n| n.map_or(char::REPLACEMENT_CHARACTER, char::from))
+            .collect()
+    }
+}
+
+/// Element types that can be stored in an [`Hdu`] created with [`Hdu::new`].
+pub trait FitsDataType: Sized {
+    /// Wrap `data` of provided `shape` into the matching [`FitsData`] variant.
+    fn new_fits_data(shape: &[usize], data: Vec<Self>) -> FitsData;
+}
+
+impl FitsDataType for u8 {
+    fn new_fits_data(shape: &[usize], data: Vec<Self>) -> FitsData {
+        FitsData::IntegersU8(FitsDataArray::new(
+            shape,
+            data.into_iter().map(Some).collect(),
+        ))
+    }
+}
+
+impl FitsDataType for Option<u8> {
+    fn new_fits_data(shape: &[usize], data: Vec<Self>) -> FitsData {
+        FitsData::IntegersU8(FitsDataArray::new(shape, data))
+    }
+}
+
+impl FitsDataType for i32 {
+    fn new_fits_data(shape: &[usize], data: Vec<Self>) -> FitsData {
+        FitsData::IntegersI32(FitsDataArray::new(
+            shape,
+            data.into_iter().map(Some).collect(),
+        ))
+    }
+}
+
+impl FitsDataType for Option<i32> {
+    fn new_fits_data(shape: &[usize], data: Vec<Self>) -> FitsData {
+        FitsData::IntegersI32(FitsDataArray::new(shape, data))
+    }
+}
+
+impl FitsDataType for u16 {
+    fn new_fits_data(shape: &[usize], data: Vec<Self>) -> FitsData {
+        FitsData::IntegersU16(FitsDataArray::new(
+            shape,
+            data.into_iter().map(Some).collect(),
+        ))
+    }
+}
+
+impl FitsDataType for Option<u16> {
+    fn new_fits_data(shape: &[usize], data: Vec<Self>) -> FitsData {
+        FitsData::IntegersU16(FitsDataArray::new(shape, data))
+    }
+}
+
+impl FitsDataType for u32 {
+    fn new_fits_data(shape: &[usize], data: Vec<Self>) -> FitsData {
+        FitsData::IntegersU32(FitsDataArray::new(
+            shape,
+            data.into_iter().map(Some).collect(),
+        ))
+    }
+}
+
+impl FitsDataType for Option<u32> {
+    fn new_fits_data(shape: &[usize], data: Vec<Self>) -> FitsData {
+        FitsData::IntegersU32(FitsDataArray::new(shape, data))
+    }
+}
+
+impl FitsDataType for i64 {
+    fn new_fits_data(shape: &[usize], data: Vec<Self>) -> FitsData {
+        FitsData::IntegersI64(FitsDataArray::new(
+            shape,
+            data.into_iter().map(Some).collect(),
+        ))
+    }
+}
+
+impl FitsDataType for Option<i64> {
+    fn new_fits_data(shape: &[usize], data: Vec<Self>) -> FitsData {
+        FitsData::IntegersI64(FitsDataArray::new(shape, data))
+    }
+}
+
+impl FitsDataType for u64 {
+    fn new_fits_data(shape: &[usize], data: Vec<Self>) -> FitsData {
+        FitsData::IntegersU64(FitsDataArray::new(
+            shape,
+            data.into_iter().map(Some).collect(),
+        ))
+    }
+}
+
+impl FitsDataType for Option<u64> {
+    fn new_fits_data(shape: &[usize], data: Vec<Self>) -> FitsData {
+        FitsData::IntegersU64(FitsDataArray::new(shape, data))
+    }
+}
+
+impl FitsDataType for f32 {
+    fn new_fits_data(shape: &[usize], data: Vec<Self>) -> FitsData {
+        FitsData::FloatingPoint32(FitsDataArray::new(shape, data))
+    }
+}
+
+impl FitsDataType for f64 {
+    fn new_fits_data(shape: &[usize], data: Vec<Self>) -> FitsData {
+        FitsData::FloatingPoint64(FitsDataArray::new(shape, data))
+    }
 }
 
 type HeaderKeyWord = String;
 
-#[derive(Debug)]
+#[derive(Clone, PartialEq, Debug)]
 struct HeaderValueComment {
     value: Option<HeaderValue>,
     comment: Option<HeaderComment>,
@@ -108,11 +410,11 @@ struct HeaderValueComment {
 /// Value stored inside the [`Hdu`] header.
 ///
 /// As defined in [FITS standard 5.2](https://archive.stsci.edu/fits/fits_standard/node30.html#SECTION00920000000000000000).
-#[derive(PartialEq, Debug)]
+#[derive(Clone, PartialEq, Debug)]
 pub enum HeaderValue {
     CharacterString(String),
     Logical(bool),
-    IntegerNumber(i32),
+    IntegerNumber(i64),
     RealFloatingNumber(f64),
     ComplexIntegerNumber(i64, i64),
     ComplexFloatingNumber(f64, f64),
@@ -120,16 +422,373 @@ pub enum HeaderValue {
 
 type HeaderComment = String;
 
+/// An iterator over the cards of an [`Hdu`] header, created by
+/// [`Hdu::cards`].
+///
+/// Yield the keyword, value and comment of each card. The text of
+/// commentary cards such as `COMMENT` and `HISTORY` is given as comment.
+/// Cards with a blank keyword are yielded with an empty keyword.
+/// Long strings continued over `CONTINUE` cards are yielded as a single
+/// card, and `HIERARCH` keywords are yielded with their `HIERARCH` prefix.
+pub struct HeaderIter<'a> {
+    iter: slice::Iter<'a, (HeaderKeyWord, Option<HeaderValueComment>)>,
+}
+
+impl<'a> Iterator for HeaderIter<'a> {
+    type Item = (&'a str, Option<&'a HeaderValue>, Option<&'a str>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.iter.next().map(|line| {
+            let value_comment = line.1.as_ref();
+            (
+                line.0.as_str(),
+                value_comment.and_then(|value_comment| value_comment.value.as_ref()),
+                value_comment.and_then(|value_comment| value_comment.comment.as_deref()),
+            )
+        })
+    }
+}
+
+/// Node in the tree formed by the `HIERARCH` keywords of an [`Hdu`].
+///
+/// See [`Hdu::hierarch`].
+#[derive(Debug)]
+pub struct HierarchNode<'a> {
+    /// Name of the node, i.e. one word of the keyword.
+    pub name: &'a str,
+    /// Value of the keyword ending at this node, if any.
+    pub value: Option<&'a HeaderValue>,
+    /// Nodes below this one, in header order.
+    pub children: Vec<HierarchNode<'a>>,
+}
+
+impl<'a> HierarchNode<'a> {
+    /// Get child node by name.
+    pub fn child(&self, name: &str) -> Option<&HierarchNode<'a>> {
+        self.children.iter().find(|node| node.name == name)
+    }
+}
+
+/// Prefix of `HIERARCH` keywords as stored in the header.
+const HIERARCH_PREFIX: &str = "HIERARCH ";
+
+#[derive(Clone)]
 struct CardImage([u8; 80]);
 
+impl fmt::Debug for CardImage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}", String::from_utf8_lossy(&self.0))
+    }
+}
+
+/// Header line with the card images it was read from or written to.
+#[derive(Debug)]
+struct OriginalLine {
+    line: (HeaderKeyWord, Option<HeaderValueComment>),
+    cards: Vec<CardImage>,
+}
+
+/// Length of a FITS logical record in bytes.
+const BLOCK_SIZE: u64 = 36 * 80;
+
+/// Move the bytes of `file` from `start` to its end by `shift` bytes towards
+/// the end of the file.
+fn shift_file_end<F: Read + Write + Seek>(
+    file: &mut F,
+    start: u64,
+    shift: u64,
+) -> Result<(), Error> {
+    let mut buffer = vec![0u8; 64 * BLOCK_SIZE as usize];
+    let mut end = file.seek(SeekFrom::End(0))?;
+    // Copy from the end, so that bytes are not overwritten before being moved
+    while end > start {
+        let len = (end - start).min(buffer.len() as u64);
+        let chunk = &mut buffer[..len as usize];
+        end -= len;
+        file.seek(SeekFrom::Start(end))?;
+        file.read_exact(chunk)?;
+        file.seek(SeekFrom::Start(end + shift))?;
+        file.write_all(chunk)?;
+    }
+    Ok(())
+}
+
+/// Length of `len` bytes once padded to a whole number of FITS blocks.
+pub(crate) fn padded_length(len: u64) -> u64 {
+    len.div_ceil(BLOCK_SIZE) * BLOCK_SIZE
+}
+
 impl Fits {
     /// Open FITS file given in provided path.
+    ///
+    /// A file compressed with gzip, bzip2 or `compress` (`.Z`) is detected
+    /// from its first bytes and decompressed in memory. The returned [`Fits`]
+    /// is then read-only.
     pub fn open<P: AsRef<Path>>(path: P) -> Result<Fits, Error> {
-        File::open(path).map(|file| Fits {
-            file: Arc::new(Mutex::new(file)),
+        Fits::open_with(path, Decompress::InMemory)
+    }
+
+    /// Open FITS file given in provided path, choosing where it is
+    /// decompressed if it is compressed.
+    ///
+    /// ```rust,no_run
+    /// use fitrs::{Decompress, Fits};
+    ///
+    /// let fits = Fits::open_with("big_file.fits.gz", Decompress::TempFile)
+    ///     .expect("Failed to open");
+    /// ```
+    pub fn open_with<P: AsRef<Path>>(path: P, decompress: Decompress) -> Result<Fits, Error> {
+        let mut file = File::open(path)?;
+        let compression = match Compression::detect(&mut file)? {
+            Some(compression) => compression,
+            None => return Ok(Fits::from_file(file)),
+        };
+        let mut decoder = compression.decoder(BufReader::new(file))?;
+        match decompress {
+            Decompress::InMemory => {
+                let mut bytes = Vec::new();
+                decoder.read_to_end(&mut bytes)?;
+                Ok(Fits::from_reader(Cursor::new(bytes)))
+            }
+            Decompress::TempFile => {
+                let mut temp_file = tempfile::tempfile()?;
+                io::copy(&mut decoder, &mut temp_file)?;
+                temp_file.seek(SeekFrom::Start(0))?;
+                Ok(Fits::from_reader(temp_file))
+            }
+        }
+    }
+
+    /// Create a new FITS file at provided path, with `primary_hdu` as its
+    /// primary HDU. Overwrite the file if it already exists.
+    ///
+    /// Return the newly created [`Fits`], which can be read from right away.
+    pub fn create<P: AsRef<Path>>(path: P, primary_hdu: Hdu) -> Result<Fits, Error> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(path)?;
+        let mut fits = Fits::from_file(file);
+        fits.write_hdu(primary_hdu, 0, true)?;
+        Ok(fits)
+    }
+
+    /// Open FITS file given in provided path for reading and writing.
+    ///
+    /// Use [`Fits::push`] to append new extensions to the file, and
+    /// [`Fits::flush`] to write back edited headers.
+    ///
+    /// Fail if the file is compressed.
+    pub fn open_mut<P: AsRef<Path>>(path: P) -> Result<Fits, Error> {
+        let mut file = OpenOptions::new().read(true).write(true).open(path)?;
+        if Compression::detect(&mut file)?.is_some() {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "Compressed FITS file cannot be opened for writing",
+            ));
+        }
+        Ok(Fits::from_file(file))
+    }
+
+    /// Append `hdu` at the end of the file, as an extension.
+    ///
+    /// The extension is an `IMAGE` unless `XTENSION` is already defined in
+    /// `hdu`'s header. If the file is empty, `hdu` is written as primary HDU.
+    /// The file must have been opened with [`Fits::create`] or
+    /// [`Fits::open_mut`].
+    pub fn push(&mut self, hdu: Hdu) -> Result<(), Error> {
+        let end = self.iter().last().map(Hdu::next_hdu_position);
+        match end {
+            Some(position) => self.write_hdu(hdu, position, false),
+            None => self.write_hdu(hdu, 0, true),
+        }
+    }
+
+    /// Write back in place the headers edited through [`Fits::get_mut`],
+    /// [`Fits::iter_mut`] or mutable indexing, and the data borrowed with
+    /// [`Hdu::read_data_mut`].
+    ///
+    /// A header that no longer fits in its blocks grows by whole blocks of
+    /// 2880 bytes, shifting the rest of the file. A header that shrinks is
+    /// padded with blank cards, keeping its size. The file must have been
+    /// opened with [`Fits::open_mut`] or [`Fits::create`].
+    ///
+    /// Edited headers are written with their mandatory keywords first, in
+    /// the order of the standard.
+    ///
+    /// Fail if a data array no longer matches the `BITPIX` and `NAXISn`
+    /// keywords of its [`Hdu`], or holds stored values out of the range of
+    /// `BITPIX`.
+    pub fn flush(&mut self) -> Result<(), FitsError> {
+        let mut file = self.file.lock().expect("Get lock");
+        let hdus = unsafe { &mut *self.hdus.get_mut().unwrap().load(Ordering::SeqCst) };
+        // Check data and put mandatory keywords first before writing anything
+        for hdu in hdus.iter().filter(|hdu| hdu.data_changed) {
+            hdu.check_data()?;
+        }
+        let mut headers = Vec::new();
+        for hdu in hdus.iter().filter(|hdu| hdu.header_changed) {
+            headers.push(hdu.normalized_header(hdu.index == Some(0))?);
+        }
+        for (hdu, header) in hdus
+            .iter_mut()
+            .filter(|hdu| hdu.header_changed)
+            .zip(headers)
+        {
+            hdu.header = header;
+        }
+        for i in 0..hdus.len() {
+            if !hdus[i].header_changed {
+                continue;
+            }
+            let mut header = Vec::new();
+            hdus[i].write_header(&mut header)?;
+            let new_length = header.len() as u64;
+            let old_length = hdus[i].data_start - hdus[i].header_start;
+            if new_length > old_length {
+                let shift = new_length - old_length;
+                shift_file_end(&mut *file, hdus[i].data_start, shift)?;
+                hdus[i].data_start += shift;
+                for hdu in &mut hdus[i + 1..] {
+                    hdu.header_start += shift;
+                    hdu.data_start += shift;
+                }
+            } else {
+                header.resize(old_length as usize, SPACE_U8);
+            }
+            file.seek(SeekFrom::Start(hdus[i].header_start))?;
+            file.write_all(&header)?;
+            hdus[i].header_changed = false;
+        }
+        for hdu in hdus.iter_mut().filter(|hdu| hdu.data_changed) {
+            file.seek(SeekFrom::Start(hdu.data_start))?;
+            hdu.write_data(&mut *file)?;
+            hdu.data_changed = false;
+        }
+        file.flush()?;
+        Ok(())
+    }
+
+    /// Read FITS from any source, such as an in-memory buffer wrapped in a
+    /// [`Cursor`](std::io::Cursor).
+    ///
+    /// The returned [`Fits`] is read-only: [`Fits::push`] and
+    /// [`Fits::flush`] fail.
+    ///
+    /// ```rust,no_run
+    /// use fitrs::Fits;
+    /// use std::io::Cursor;
+    ///
+    /// # let bytes: Vec<u8> = Vec::new();
+    /// let fits = Fits::from_reader(Cursor::new(bytes));
+    /// println!("{:?}", fits[0].read_data());
+    /// ```
+    pub fn from_reader<R: Read + Seek + Send + 'static>(reader: R) -> Fits {
+        Fits::from_storage(Box::new(ReadOnly(reader)))
+    }
+
+    fn from_file(file: File) -> Fits {
+        Fits::from_storage(Box::new(file))
+    }
+
+    /// Open FITS file given in provided path, mapped in memory.
+    ///
+    /// The data units can then be borrowed without copy with
+    /// [`Hdu::raw_data`] and [`Hdu::view`], which only load the pages of the
+    /// file that are accessed. Sections are read straight from memory too.
+    /// The returned [`Fits`] is read-only. Fail if the file is compressed.
+    ///
+    /// # Safety
+    ///
+    /// The file must not be modified or truncated, by this process or any
+    /// other, while the returned [`Fits`] or anything borrowed from it is
+    /// alive. Else the bytes behind shared references would change, which
+    /// is undefined behavior, and reading past a truncated end would crash
+    /// the process.
+    ///
+    /// ```rust,no_run
+    /// use fitrs::Fits;
+    ///
+    /// // The file is not modified while it is mapped
+    /// let fits = unsafe { Fits::open_mmap("cube.fits") }.expect("Failed to open");
+    /// let cube = fits[0].view::<f32>().expect("Not mapped");
+    /// println!("{:?}", cube.get_pixel(&[100, 200, 3]));
+    /// ```
+    #[cfg(feature = "mmap")]
+    pub unsafe fn open_mmap<P: AsRef<Path>>(path: P) -> Result<Fits, Error> {
+        let mut file = File::open(path)?;
+        if Compression::detect(&mut file)?.is_some() {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "Compressed FITS file cannot be memory-mapped",
+            ));
+        }
+        Ok(Fits::from_storage(Box::new(MmapStorage::new(&file)?)))
+    }
+
+    fn from_storage(storage: Box<dyn Storage>) -> Fits {
+        Fits {
+            file: Arc::new(Mutex::new(storage)),
             hdus: Mutex::new(AtomicPtr::new(Box::into_raw(Box::new(Vec::new())))),
             total_hdu_count: RwLock::new(None),
-        })
+        }
+    }
+
+    /// Write `hdu` at `position` in file and add it at the end of the cache.
+    ///
+    /// The data unit of a table or random groups read from a file is copied
+    /// as is, with its heap. Such an [`Hdu`] built in memory cannot be
+    /// written if it has a heap or random groups.
+    fn write_hdu(&mut self, mut hdu: Hdu, position: u64, primary: bool) -> Result<(), Error> {
+        let raw_data = if hdu.is_image()? {
+            None
+        } else if hdu.file.is_some() {
+            let len = hdu.data_byte_length()?;
+            Some(hdu.read_raw_data(len as usize)?)
+        } else if hdu.optional_size_keyword("PCOUNT", 0)? != 0
+            || hdu.optional_size_keyword("GCOUNT", 1)? != 1
+            || hdu.value("GROUPS") == Some(&HeaderValue::Logical(true))
+        {
+            return Err(FitsError::NotAnImage { hdu: hdu.index }.into());
+        } else {
+            None
+        };
+        hdu.header = hdu.normalized_header(primary)?;
+        {
+            let mut file_lock = self.file.lock().expect("Get lock");
+            file_lock.seek(SeekFrom::Start(position))?;
+            hdu.header_start = position;
+            hdu.data_start = position + hdu.write_header(&mut *file_lock)?;
+            hdu.header_changed = false;
+            hdu.data_changed = false;
+            match raw_data {
+                Some(mut raw_data) => {
+                    // ASCII tables are padded with spaces
+                    let fill = match hdu.value("XTENSION") {
+                        Some(HeaderValue::CharacterString(xtension))
+                            if xtension.trim_end() == "TABLE" =>
+                        {
+                            SPACE_U8
+                        }
+                        _ => 0,
+                    };
+                    let len = padded_length(raw_data.len() as u64);
+                    raw_data.resize(len as usize, fill);
+                    file_lock.write_all(&raw_data)?;
+                }
+                None => hdu.write_data(&mut *file_lock)?,
+            }
+            file_lock.flush()?;
+        }
+        hdu.file = Some(self.file.clone());
+        let hdu_guard = self.hdus_guard();
+        let hdus = unsafe { &mut *hdu_guard.load(Ordering::SeqCst) };
+        hdu.index = Some(hdus.len());
+        hdus.push(hdu);
+        *self.total_hdu_count.write().unwrap() = Some(hdus.len());
+        Ok(())
     }
 
     /// Iterate over references to [`Hdu`]s.
@@ -141,6 +800,17 @@ impl Fits {
         }
     }
 
+    /// Iterate over references to [`Hdu`]s, returning an error if an
+    /// [`Hdu`] cannot be read, for example if the file is truncated.
+    ///
+    /// [`Fits::iter`] panics on such errors.
+    pub fn try_iter(&self) -> FitsTryIter<'_> {
+        FitsTryIter {
+            iter: self.iter(),
+            failed: false,
+        }
+    }
+
     /// Iterate over mutable references to [`Hdu`]s.
     pub fn iter_mut(&mut self) -> FitsIterMut {
         FitsIterMut {
@@ -150,6 +820,15 @@ impl Fits {
         }
     }
 
+    /// Iterate over mutable references to [`Hdu`]s, returning an error if an
+    /// [`Hdu`] cannot be read. See [`Fits::try_iter`].
+    pub fn try_iter_mut(&mut self) -> FitsTryIterMut<'_> {
+        FitsTryIterMut {
+            iter: self.iter_mut(),
+            failed: false,
+        }
+    }
+
     /// Force-read the whole FITS file and cache it.
     ///
     /// Beware of the size of the file you are loading before doing that.
@@ -169,6 +848,18 @@ impl Fits {
         None
     }
 
+    /// Get reference to [`Hdu`] by index, or an error if an [`Hdu`] cannot be
+    /// read before reaching it. Use `0` for primary HDU.
+    pub fn try_get(&self, index: usize) -> Result<Option<&Hdu>, FitsError> {
+        for (i, hdu) in self.try_iter().enumerate() {
+            let hdu = hdu?;
+            if i == index {
+                return Ok(Some(hdu));
+            }
+        }
+        Ok(None)
+    }
+
     /// Get mutable reference to [`Hdu`] by index. Use `0` for primary HDU.
     pub fn get_mut(&mut self, index: usize) -> Option<&mut Hdu> {
         for (i, hdu) in self.iter_mut().enumerate() {
@@ -179,6 +870,18 @@ impl Fits {
         None
     }
 
+    /// Get mutable reference to [`Hdu`] by index, or an error if an [`Hdu`]
+    /// cannot be read before reaching it. Use `0` for primary HDU.
+    pub fn try_get_mut(&mut self, index: usize) -> Result<Option<&mut Hdu>, FitsError> {
+        for (i, hdu) in self.try_iter_mut().enumerate() {
+            let hdu = hdu?;
+            if i == index {
+                return Ok(Some(hdu));
+            }
+        }
+        Ok(None)
+    }
+
     /// Get reference to [`Hdu`] by `EXTNAME`. Defined in [FIST standard 5.4.2.6](https://archive.stsci.edu/fits/fits_standard/node40.html#SECTION00942000000000000000)
     pub fn get_by_name(&self, index: &str) -> Option<&Hdu> {
         let value = Some(HeaderValue::CharacterString(String::from(index)));
@@ -190,6 +893,19 @@ impl Fits {
         None
     }
 
+    /// Get reference to [`Hdu`] by `EXTNAME`, or an error if an [`Hdu`] cannot
+    /// be read before finding it.
+    pub fn try_get_by_name(&self, index: &str) -> Result<Option<&Hdu>, FitsError> {
+        let value = Some(HeaderValue::CharacterString(String::from(index)));
+        for hdu in self.try_iter() {
+            let hdu = hdu?;
+            if hdu.value("EXTNAME") == value.as_ref() {
+                return Ok(Some(hdu));
+            }
+        }
+        Ok(None)
+    }
+
     /// Get mutable reference to [`Hdu`] by `EXTNAME`. Defined in [FIST standard 5.4.2.6](https://archive.stsci.edu/fits/fits_standard/node40.html#SECTION00942000000000000000)
     pub fn get_mut_by_name(&mut self, index: &str) -> Option<&mut Hdu> {
         let value = Some(HeaderValue::CharacterString(String::from(index)));
@@ -201,6 +917,19 @@ impl Fits {
         None
     }
 
+    /// Get mutable reference to [`Hdu`] by `EXTNAME`, or an error if an
+    /// [`Hdu`] cannot be read before finding it.
+    pub fn try_get_mut_by_name(&mut self, index: &str) -> Result<Option<&mut Hdu>, FitsError> {
+        let value = Some(HeaderValue::CharacterString(String::from(index)));
+        for hdu in self.try_iter_mut() {
+            let hdu = hdu?;
+            if hdu.value("EXTNAME") == value.as_ref() {
+                return Ok(Some(hdu));
+            }
+        }
+        Ok(None)
+    }
+
     fn hdus_guard(&self) -> MutexGuard<AtomicPtr<Vec<Hdu>>> {
         self.hdus.lock().unwrap()
     }
@@ -278,31 +1007,25 @@ impl IntoIterator for Fits {
         FitsIntoIter {
             fits: self,
             position: 0,
+            count: 0,
         }
     }
 }
 
 trait MovableCursor {
-    fn file(&self) -> MutexGuard<File>;
+    fn file(&self) -> MutexGuard<'_, Box<dyn Storage>>;
     fn position(&self) -> u64;
 
-    fn tell(file_lock: &mut MutexGuard<File>) -> u64 {
-        file_lock
-            .seek(SeekFrom::Current(0))
-            .expect("Could not get cursor position!")
-    }
-
-    fn set_position(&self) -> MutexGuard<File> {
+    fn set_position(&self) -> Result<MutexGuard<'_, Box<dyn Storage>>, Error> {
         let position = self.position();
         let mut lock = self.file();
-        lock.seek(SeekFrom::Start(position))
-            .expect("Could not set position!");
-        lock
+        lock.seek(SeekFrom::Start(position))?;
+        Ok(lock)
     }
 }
 
 impl MovableCursor for FitsIntoIter {
-    fn file(&self) -> MutexGuard<File> {
+    fn file(&self) -> MutexGuard<'_, Box<dyn Storage>> {
         self.fits.file.lock().expect("Get lock")
     }
     fn position(&self) -> u64 {
@@ -311,7 +1034,7 @@ impl MovableCursor for FitsIntoIter {
 }
 
 impl<'f> MovableCursor for FitsIter<'f> {
-    fn file(&self) -> MutexGuard<File> {
+    fn file(&self) -> MutexGuard<'_, Box<dyn Storage>> {
         self.fits.file.lock().expect("Get lock")
     }
     fn position(&self) -> u64 {
@@ -320,7 +1043,7 @@ impl<'f> MovableCursor for FitsIter<'f> {
 }
 
 impl<'f> MovableCursor for FitsIterMut<'f> {
-    fn file(&self) -> MutexGuard<File> {
+    fn file(&self) -> MutexGuard<'_, Box<dyn Storage>> {
         self.fits.file.lock().expect("Get lock")
     }
     fn position(&self) -> u64 {
@@ -331,8 +1054,12 @@ impl<'f> MovableCursor for FitsIterMut<'f> {
 impl Iterator for FitsIntoIter {
     type Item = Hdu;
     fn next(&mut self) -> Option<Self::Item> {
-        self.read_next_hdu().map(|(hdu, next_position)| {
+        let next = self
+            .read_next_hdu(self.count)
+            .unwrap_or_else(|e| panic!("{}", e));
+        next.map(|(hdu, next_position)| {
             self.position = next_position;
+            self.count += 1;
             hdu
         })
     }
@@ -341,45 +1068,30 @@ impl Iterator for FitsIntoIter {
 trait IterableOverHdu: MovableCursor {
     fn file_rc(&self) -> &FileRc;
 
-    fn read_next_hdu(&self) -> Option<(Hdu, u64)> {
-        let (header, data_start_position) = {
+    /// Read header of the [`Hdu`] at current position, which is the HDU
+    /// number `index` in file, and return it with the position of the next
+    /// [`Hdu`].
+    ///
+    /// Return [`None`] at the end of the file.
+    fn read_next_hdu(&self, index: usize) -> Result<Option<(Hdu, u64)>, FitsError> {
+        let position = self.position();
+        let hdu = {
             // Get file lock
-            let mut file_lock = self.set_position();
-            let mut line = CardImage::new();
-            let mut line_count = 0;
-            let mut header = Vec::new();
-            let mut end = false;
-            while (line_count % 36) != 0 || !end {
-                match file_lock.read_exact(&mut line.0) {
-                    Ok(_) => {
-                        line.to_header_key_value().map(|(key, val)| {
-                            if key == "END" {
-                                end = true;
-                            }
-                            header.push((key, val));
-                        });
-                    }
-                    Err(_) => return None,
-                };
-                line_count += 1;
-            }
-            let data_start_position = Self::tell(&mut file_lock);
-            (header, data_start_position)
+            let mut file_lock = self.set_position().map_err(|error| FitsError::Io {
+                offset: Some(position),
+                error,
+            })?;
+            Hdu::read_header(&mut *file_lock, index, position)?.map(|mut hdu| {
+                hdu.mapping = file_lock.mapping();
+                hdu
+            })
         };
         // Lock released
-        let hdu = Hdu {
-            header: header,
-            data_start: data_start_position,
-            file: self.file_rc().clone(),
-            data: RwLock::new(None),
-        };
-        let len = hdu.data_byte_length().unwrap();
-        let mut next_position = data_start_position + (len as u64);
-        /* Compute next position to go to end of record */
-        while (next_position % (36 * 80)) != 0 {
-            next_position += 1;
-        }
-        Some((hdu, next_position))
+        Ok(hdu.map(|mut hdu| {
+            hdu.file = Some(self.file_rc().clone());
+            let next_position = hdu.next_hdu_position();
+            (hdu, next_position)
+        }))
     }
 }
 
@@ -401,700 +1113,4718 @@ impl<'f> IterableOverHdu for FitsIterMut<'f> {
     }
 }
 
-impl<'f> Iterator for FitsIter<'f> {
-    type Item = &'f Hdu;
-    fn next(&mut self) -> Option<&'f Hdu> {
+impl<'f> FitsIter<'f> {
+    fn try_next(&mut self) -> Result<Option<&'f Hdu>, FitsError> {
         if let Some(hdu_count) = *self.fits.total_hdu_count.read().unwrap() {
             if self.count >= hdu_count {
-                return None;
+                return Ok(None);
             }
         }
         let hdu_guard = self.fits.hdus_guard();
         let hdus = unsafe { &mut *hdu_guard.load(Ordering::SeqCst) };
         if self.count < hdus.len() {
             self.count += 1;
-            return Some(&hdus[self.count - 1]);
+            self.position = hdus[self.count - 1].next_hdu_position();
+            return Ok(Some(&hdus[self.count - 1]));
         }
-        if let Some((hdu, next_position)) = self.read_next_hdu() {
+        if let Some((hdu, next_position)) = self.read_next_hdu(self.count)? {
             self.count += 1;
             self.position = next_position;
             hdus.push(hdu);
-            hdus.last()
+            Ok(hdus.last())
         } else {
             *self.fits.total_hdu_count.write().unwrap() = Some(self.count);
-            None
+            Ok(None)
         }
     }
 }
 
-impl<'f> Iterator for FitsIterMut<'f> {
-    type Item = &'f mut Hdu;
-    fn next(&mut self) -> Option<&'f mut Hdu> {
+impl<'f> Iterator for FitsIter<'f> {
+    type Item = &'f Hdu;
+    fn next(&mut self) -> Option<&'f Hdu> {
+        self.try_next().unwrap_or_else(|e| panic!("{}", e))
+    }
+}
+
+impl<'f> Iterator for FitsTryIter<'f> {
+    type Item = Result<&'f Hdu, FitsError>;
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None;
+        }
+        match self.iter.try_next() {
+            Ok(hdu) => hdu.map(Ok),
+            Err(e) => {
+                self.failed = true;
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+impl<'f> FitsIterMut<'f> {
+    fn try_next(&mut self) -> Result<Option<&'f mut Hdu>, FitsError> {
         if let Some(hdu_count) = *self.fits.total_hdu_count.read().unwrap() {
             if self.count >= hdu_count {
-                return None;
+                return Ok(None);
             }
         }
         let hdu_guard = self.fits.hdus_guard();
         let hdus = unsafe { &mut *hdu_guard.load(Ordering::SeqCst) };
         if self.count < hdus.len() {
             self.count += 1;
-            return Some(&mut hdus[self.count - 1]);
+            self.position = hdus[self.count - 1].next_hdu_position();
+            return Ok(Some(&mut hdus[self.count - 1]));
         }
-        if let Some((hdu, next_position)) = self.read_next_hdu() {
+        if let Some((hdu, next_position)) = self.read_next_hdu(self.count)? {
             self.count += 1;
             self.position = next_position;
             hdus.push(hdu);
-            hdus.last_mut()
+            Ok(hdus.last_mut())
         } else {
             *self.fits.total_hdu_count.write().unwrap() = Some(self.count);
-            None
+            Ok(None)
         }
     }
 }
 
-impl Hdu {
-    /// Get [`HeaderValue`] by key. Return [`None`] if value is not found
-    /// in [`Hdu`].
-    pub fn value(&self, key: &str) -> Option<&HeaderValue> {
-        for line in self.header.iter() {
-            if line.0 == key {
-                return line.1
-                    .as_ref()
-                    .and_then(|value_comment| value_comment.value.as_ref());
+impl<'f> Iterator for FitsIterMut<'f> {
+    type Item = &'f mut Hdu;
+    fn next(&mut self) -> Option<&'f mut Hdu> {
+        self.try_next().unwrap_or_else(|e| panic!("{}", e))
+    }
+}
+
+impl<'f> Iterator for FitsTryIterMut<'f> {
+    type Item = Result<&'f mut Hdu, FitsError>;
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None;
+        }
+        match self.iter.try_next() {
+            Ok(hdu) => hdu.map(Ok),
+            Err(e) => {
+                self.failed = true;
+                Some(Err(e))
             }
         }
-        None
     }
+}
 
-    fn value_as_integer_number(&self, key: &str) -> Option<i32> {
-        self.value(key).and_then(|val| match val {
-            &HeaderValue::IntegerNumber(n) => Some(n),
-            _ => None,
-        })
+impl Hdu {
+    /// Create a new [`Hdu`] holding `data`, an array of provided `shape`.
+    ///
+    /// Mandatory keywords (`BITPIX`, `NAXIS` and `NAXISn`) are generated from
+    /// the data. Use [`Hdu::insert`] to add other keywords.
+    ///
+    /// Undefined integer values are written as `BLANK`, a value chosen
+    /// among the values absent from `data`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the shape does not match the length of `data`, or if no
+    /// value is left for `BLANK`. See [`Hdu::try_new`] for a fallible
+    /// version.
+    pub fn new<T: FitsDataType>(shape: &[usize], data: Vec<T>) -> Hdu {
+        Hdu::try_new(shape, data).unwrap_or_else(|e| panic!("{}", e))
     }
 
-    fn naxis(&self) -> Option<Vec<usize>> {
-        self.value_as_integer_number("NAXIS").and_then(|naxis| {
-            let mut vec = Vec::new();
-            for i in 1..(naxis + 1) {
-                let mut key = String::from("NAXIS");
-                key.push_str(&i.to_string());
-                match self.value_as_integer_number(&key) {
-                    None => return None,
-                    Some(k) => vec.push(k as usize),
-                }
-            }
-            Some(vec)
-        })
+    /// Create a new [`Hdu`] holding `data`, an array of provided `shape`.
+    ///
+    /// Return [`FitsError::DataMismatch`] if the shape does not match the
+    /// length of `data`, and [`FitsError::NoFreeBlank`] if `data` has
+    /// undefined values but uses every value of its type.
+    pub fn try_new<T: FitsDataType>(shape: &[usize], data: Vec<T>) -> Result<Hdu, FitsError> {
+        if shape.iter().product::<usize>() != data.len() {
+            return Err(FitsError::DataMismatch { hdu: None });
+        }
+        Hdu::with_data(T::new_fits_data(shape, data))
     }
 
-    fn data_length(&self) -> Option<usize> {
-        self.naxis().map(|naxis| {
-            let mut len = 0;
-            for (i, k) in naxis.iter().enumerate() {
-                if i == 0 {
-                    len += *k as usize;
-                } else {
-                    len *= *k as usize;
+    /// Create a new [`Hdu`] without any data array.
+    ///
+    /// Typically used as primary HDU of a file only holding extensions.
+    pub fn empty() -> Hdu {
+        Hdu::with_data(FitsData::IntegersU8(FitsDataArray::new(&[], Vec::new())))
+            .expect("No BLANK needed without data")
+    }
+
+    fn with_data(data: FitsData) -> Result<Hdu, FitsError> {
+        let mut hdu = Hdu {
+            header: Vec::new(),
+            header_changed: false,
+            original: Vec::new(),
+            data_changed: false,
+            header_start: 0,
+            data_start: 0,
+            index: None,
+            file: None,
+            mapping: None,
+            data: RwLock::new(None),
+        };
+        {
+            let (bitpix, shape, bzero, blank) = match data {
+                FitsData::IntegersU8(ref array) => {
+                    let stored = array.data.iter().map(|n| n.map(i64::from));
+                    let blank = free_blank(stored, u8::MAX, u8::MIN, u8::MAX)?;
+                    (8, &array.shape, None, blank)
+                }
+                FitsData::IntegersI32(ref array) => {
+                    let stored = array.data.iter().map(|n| n.map(i64::from));
+                    let blank = free_blank(stored, i32::MIN, i32::MIN, i32::MAX)?;
+                    (32, &array.shape, None, blank)
+                }
+                FitsData::IntegersU16(ref array) => {
+                    let stored = array
+                        .data
+                        .iter()
+                        .map(|n| n.map(|n| (n ^ 0x8000) as i16 as i64));
+                    let bzero = HeaderValue::from(32768);
+                    let blank = free_blank(stored, i16::MAX, i16::MIN, i16::MAX)?;
+                    (16, &array.shape, Some(bzero), blank)
+                }
+                FitsData::IntegersU32(ref array) => {
+                    let stored = array
+                        .data
+                        .iter()
+                        .map(|n| n.map(|n| (n ^ 0x8000_0000) as i32 as i64));
+                    let bzero = HeaderValue::from(2147483648.0);
+                    let blank = free_blank(stored, i32::MAX, i32::MIN, i32::MAX)?;
+                    (32, &array.shape, Some(bzero), blank)
                 }
+                FitsData::IntegersI64(ref array) => {
+                    let stored = array.data.iter().cloned();
+                    let blank = free_blank(stored, i64::MIN, i64::MIN, i64::MAX)?;
+                    (64, &array.shape, None, blank)
+                }
+                FitsData::IntegersU64(ref array) => {
+                    let stored = array
+                        .data
+                        .iter()
+                        .map(|n| n.map(|n| (n ^ 0x8000_0000_0000_0000) as i64));
+                    let bzero = HeaderValue::from(9223372036854775808.0);
+                    let blank = free_blank(stored, i64::MAX, i64::MIN, i64::MAX)?;
+                    (64, &array.shape, Some(bzero), blank)
+                }
+                FitsData::FloatingPoint32(ref array) => (-32, &array.shape, None, None),
+                FitsData::FloatingPoint64(ref array) => (-64, &array.shape, None, None),
+            };
+            hdu.set_value(String::from("SIMPLE"), true);
+            hdu.set_value(String::from("BITPIX"), bitpix);
+            let axis =
+                |n: usize| i64::try_from(n).map_err(|_| FitsError::DataMismatch { hdu: None });
+            hdu.set_value(String::from("NAXIS"), axis(shape.len())?);
+            for (i, n) in shape.iter().enumerate() {
+                hdu.set_value(format!("NAXIS{}", i + 1), axis(*n)?);
             }
-            len
-        })
+            if let Some(bzero) = bzero {
+                hdu.insert("BSCALE", 1);
+                hdu.insert("BZERO", bzero);
+            }
+            if let Some(blank) = blank {
+                hdu.insert("BLANK", blank);
+            }
+        }
+        *hdu.data.get_mut().unwrap() = Some(data);
+        Ok(hdu)
     }
 
-    fn data_byte_length(&self) -> Option<usize> {
-        self.data_length().and_then(|len| {
-            self.value_as_integer_number("BITPIX").map(|bit| {
-                let bit = if bit < 0 { -bit } else { bit };
-                len * (bit as usize / 8)
-            })
-        })
+    /// Insert `value` in header for provided `key`.
+    ///
+    /// Replace the value if `key` is already present, keeping its comment.
+    /// Else the keyword is added at the end of the header, in place of a
+    /// blank card reserved there if any.
+    ///
+    /// Keys starting with `HIERARCH `, such as `"HIERARCH ESO DET CHIP
+    /// TEMP"`, are stored as `HIERARCH` keywords.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `key` is not a valid keyword, see [`Hdu::try_insert`].
+    pub fn insert<T: Into<HeaderValue>>(&mut self, key: &str, value: T) {
+        self.try_insert(key, value)
+            .unwrap_or_else(|e| panic!("{}", e))
     }
 
-    fn is_data_cached(&self) -> bool {
-        self.data.read().unwrap().is_some()
+    /// Insert `value` in header for provided `key`, like [`Hdu::insert`].
+    ///
+    /// Fail if `key` is neither a standard keyword, made of at most 8
+    /// uppercase letters, digits, hyphens or underscores, nor a keyword
+    /// starting with `HIERARCH `. Fail as well if `key` is a mandatory
+    /// keyword such as `BITPIX` or `NAXISn`, which are set from the data, or
+    /// if `value` is a string with other characters than printable ASCII.
+    ///
+    /// `XTENSION` can only be inserted in an HDU not read from a file, to
+    /// set the type of the extension written by [`Fits::push`].
+    pub fn try_insert<T: Into<HeaderValue>>(
+        &mut self,
+        key: &str,
+        value: T,
+    ) -> Result<(), FitsError> {
+        let value = value.into();
+        let key = self.insertion_key(key, &value)?;
+        self.set_value(key, value);
+        Ok(())
     }
 
-    fn data(&self) -> Option<&FitsData> {
-        if let Some(ref data) = *self.data.read().unwrap() {
-            let data = data as *const FitsData;
-            Some(unsafe { &*data })
-        } else {
-            None
+    /// Set `value` for `key`, as stored in the header, without checking
+    /// that they can be inserted.
+    fn set_value<T: Into<HeaderValue>>(&mut self, key: String, value: T) {
+        let value = Some(value.into());
+        for line in self.header_mut().iter_mut() {
+            if line.0 == key {
+                match line.1 {
+                    Some(ref mut value_comment) => value_comment.value = value,
+                    None => {
+                        line.1 = Some(HeaderValueComment {
+                            value,
+                            comment: None,
+                        })
+                    }
+                }
+                return;
+            }
         }
+        self.push_line((
+            key,
+            Some(HeaderValueComment {
+                value,
+                comment: None,
+            }),
+        ));
     }
 
-    /// Get data array stored in the [`Hdu`].
-    pub fn read_data(&self) -> &FitsData {
-        if self.is_data_cached() {
-            self.data().unwrap()
+    /// Add `line` at the end of the header, in place of the first of the
+    /// blank cards reserved there, if any.
+    fn push_line(&mut self, line: (HeaderKeyWord, Option<HeaderValueComment>)) {
+        let header = self.header_mut();
+        let blank_count = header
+            .iter()
+            .rev()
+            .take_while(|line| line.0.is_empty() && line.1.is_none())
+            .count();
+        if blank_count > 0 {
+            let first_blank = header.len() - blank_count;
+            header[first_blank] = line;
         } else {
-            self.read_data_force()
+            header.push(line);
         }
     }
 
-    fn read_data_force(&self) -> &FitsData {
-        let bitpix = self.value_as_integer_number("BITPIX")
-            .expect("BITPIX is present");
-        let data = match bitpix {
-            8 => FitsData::Characters(self.inner_read_data_force(|file, len| {
-                let mut buf = vec![0u8; len];
-                file.read_exact(&mut buf).expect("Read array");
-                buf.into_iter().map(|n| n as char).collect()
-            })),
-            16 => {
-                let blank = self.value_as_integer_number("BLANK");
-                FitsData::IntegersI32(self.inner_read_data_force(|file, len| {
-                    let mut buf = vec![0i16; len];
-                    file.read_i16_into::<BigEndian>(&mut buf)
-                        .expect("Read array");
-                    if blank.is_some() {
-                        let blank = blank.unwrap() as i16;
-                        buf.into_iter()
-                            .map(|n| if n == blank { None } else { Some(n as i32) })
-                            .collect()
-                    } else {
-                        buf.into_iter().map(|n| Some(n as i32)).collect()
-                    }
-                }))
-            }
-            32 => {
-                let blank = self.value_as_integer_number("BLANK");
-                FitsData::IntegersI32(self.inner_read_data_force(|file, len| {
-                    let mut buf = vec![0i32; len];
-                    file.read_i32_into::<BigEndian>(&mut buf)
-                        .expect("Read array");
-                    if blank.is_some() {
-                        let blank = blank.unwrap();
-                        buf.into_iter()
-                            .map(|n| if n == blank { None } else { Some(n) })
-                            .collect()
-                    } else {
-                        buf.into_iter().map(Some).collect()
-                    }
-                }))
-            }
-            -32 => FitsData::FloatingPoint32(self.inner_read_data_force(|file, len| {
-                let mut buf = vec![0f32; len];
-                file.read_f32_into::<BigEndian>(&mut buf)
-                    .expect("Read array");
-                buf
-            })),
-            -64 => FitsData::FloatingPoint64(self.inner_read_data_force(|file, len| {
-                let mut buf = vec![0f64; len];
-                file.read_f64_into::<BigEndian>(&mut buf)
-                    .expect("Read array");
-                buf
-            })),
-            _ => panic!("Unexpected value for BITPIX"),
-        };
-        let mut out = self.data.write().unwrap();
-        *out = Some(data);
-        // Release write-lock to be able to read and return back the data
-        drop(out);
-        self.data().unwrap()
+    /// Get [`HeaderValue`] by key. Return [`None`] if value is not found
+    /// in [`Hdu`].
+    ///
+    /// Values of `HIERARCH` keywords are found by their full name, with or
+    /// without the `HIERARCH` prefix, and with words separated by spaces or
+    /// dots: `"HIERARCH ESO DET CHIP TEMP"`, `"ESO DET CHIP TEMP"` and
+    /// `"ESO.DET.CHIP.TEMP"` are equivalent.
+    pub fn value(&self, key: &str) -> Option<&HeaderValue> {
+        if let Some(line) = self.line(key) {
+            return line
+                .1
+                .as_ref()
+                .and_then(|value_comment| value_comment.value.as_ref());
+        }
+        None
     }
 
-    fn inner_read_data_force<F, T>(&self, read: F) -> FitsDataArray<T>
-    where
-        F: Fn(&mut File, usize) -> Vec<T>,
-    {
-        let naxis = self.naxis().expect("Get NAXIS");
-        let length = naxis.iter().product();
-        let mut file_lock = self.file.lock().expect("Get file lock");
-        file_lock
-            .seek(SeekFrom::Start(self.data_start))
-            .expect("Set data position");
-        FitsDataArray::new(&naxis, read(&mut *file_lock, length))
+    /// Header, marked as changed so that [`Fits::flush`] writes it back.
+    fn header_mut(&mut self) -> &mut Vec<(HeaderKeyWord, Option<HeaderValueComment>)> {
+        self.header_changed = true;
+        &mut self.header
     }
-}
-
-const EQUAL_U8: u8 = '=' as u8;
-const SPACE_U8: u8 = ' ' as u8;
-const SLASH_U8: u8 = '/' as u8;
-const QUOTE_U8: u8 = '\'' as u8;
-const T_U8: u8 = 'T' as u8;
-const F_U8: u8 = 'F' as u8;
 
-impl HeaderValue {
-    fn new(value: &[u8]) -> Option<HeaderValue> {
-        HeaderValue::new_character_string(value)
-            .or_else(|| HeaderValue::new_logical(value))
-            .or_else(|| HeaderValue::new_integer(value))
-            .or_else(|| HeaderValue::new_real_floating(value))
+    /// Header line for `key`, looking for a `HIERARCH` keyword as well.
+    fn line(&self, key: &str) -> Option<&(HeaderKeyWord, Option<HeaderValueComment>)> {
+        self.position(key).map(|i| &self.header[i])
     }
 
-    fn new_character_string(subcard: &[u8]) -> Option<HeaderValue> {
-        if subcard[0] != QUOTE_U8 {
-            return None;
-        }
-        let subcard = &subcard[1..];
-        let mut s = String::new();
-        let mut prev_single_quote = false;
-        let mut white_space_count = 0;
-        for (i, c) in subcard.iter().enumerate() {
-            if prev_single_quote {
-                if *c == QUOTE_U8 {
-                    s.push(*c as char);
-                    prev_single_quote = false;
-                } else {
-                    break;
-                }
-            } else if *c == QUOTE_U8 {
-                prev_single_quote = true;
-                continue;
-            } else if i > 0 && *c == SPACE_U8 {
-                white_space_count += 1;
-            } else {
-                while white_space_count > 0 {
-                    s.push(' ');
-                    white_space_count -= 1;
-                }
-                s.push(*c as char);
-            }
+    /// Position in header of the first line for `key`, looking for a
+    /// `HIERARCH` keyword as well.
+    fn position(&self, key: &str) -> Option<usize> {
+        let mut words = key_words(key);
+        if words.next() == Some("HIERARCH") && words.clone().next().is_some() {
+            return self
+                .header
+                .iter()
+                .position(|line| hierarch_matches(&line.0, words.clone()));
         }
-        Some(HeaderValue::CharacterString(s))
+        self.header
+            .iter()
+            .position(|line| line.0 == key)
+            .or_else(|| {
+                self.header
+                    .iter()
+                    .position(|line| hierarch_matches(&line.0, key_words(key)))
+            })
     }
 
-    fn new_logical(value: &[u8]) -> Option<HeaderValue> {
-        let mut b = false;
-        let logical_constant_column = 30 - 10 - 1;
-        for (i, c) in value.iter().enumerate() {
-            if i == logical_constant_column {
-                if *c == T_U8 {
-                    b = true;
-                } else if *c == F_U8 {
-                    b = false;
-                } else {
-                    return None;
-                }
-            } else if *c != SPACE_U8 {
-                return None;
-            }
-        }
-        Some(HeaderValue::Logical(b))
+    /// Position in header of the first line for `key`, or an error if `key`
+    /// is missing.
+    fn existing_position(&self, key: &str) -> Result<usize, FitsError> {
+        self.position(key).ok_or_else(|| self.missing_keyword(key))
     }
 
-    fn new_integer(value: &[u8]) -> Option<HeaderValue> {
-        from_utf8(value)
-            .ok()
-            .and_then(|string| {
-                let trimmed = string.trim();
-                i32::from_str_radix(trimmed, 10).ok()
-            })
-            .map(HeaderValue::IntegerNumber)
+    /// Insert `value` for `key` just before the card of keyword `before`.
+    ///
+    /// If `key` is already present, it is moved, keeping its comment. Fail
+    /// if `before` is missing, if the card would land among the mandatory
+    /// keywords that start the header, or if `key` and `value` cannot be
+    /// inserted as with [`Hdu::try_insert`].
+    pub fn insert_before<T: Into<HeaderValue>>(
+        &mut self,
+        before: &str,
+        key: &str,
+        value: T,
+    ) -> Result<(), FitsError> {
+        self.insert_at(before, 0, key, value.into())
     }
 
-    fn new_real_floating(value: &[u8]) -> Option<HeaderValue> {
-        from_utf8(value)
-            .ok()
-            .and_then(|string| {
-                let trimmed = string.trim();
-                f64::from_str(trimmed).ok()
-            })
-            .map(HeaderValue::RealFloatingNumber)
+    /// Insert `value` for `key` just after the card of keyword `after`.
+    ///
+    /// If `key` is already present, it is moved, keeping its comment. Fail
+    /// if `after` is missing, if the card would land among the mandatory
+    /// keywords that start the header, or if `key` and `value` cannot be
+    /// inserted as with [`Hdu::try_insert`].
+    pub fn insert_after<T: Into<HeaderValue>>(
+        &mut self,
+        after: &str,
+        key: &str,
+        value: T,
+    ) -> Result<(), FitsError> {
+        self.insert_at(after, 1, key, value.into())
     }
-}
 
-impl HeaderValueComment {
-    fn new(value_comment: &[u8]) -> HeaderValueComment {
-        let mut value_comment_iter = value_comment.split(|c| *c == SLASH_U8);
-        let value_slice = value_comment_iter.next();
-        let comment_slice = value_comment_iter.next();
-        HeaderValueComment {
-            value: value_slice.and_then(HeaderValue::new),
-            comment: comment_slice.map(|slice| {
-                let mut comment = HeaderComment::new();
-                for c in slice {
-                    comment.push(*c as char);
-                }
-                String::from(comment.trim())
-            }),
+    /// Insert `value` for `key` at `offset` cards from the card of
+    /// keyword `anchor`.
+    fn insert_at(
+        &mut self,
+        anchor: &str,
+        offset: usize,
+        key: &str,
+        value: HeaderValue,
+    ) -> Result<(), FitsError> {
+        let header_key = self.insertion_key(key, &value)?;
+        let anchor_position = self.existing_position(anchor)?;
+        if self.position(key) == Some(anchor_position) {
+            return self.try_insert(key, value);
+        }
+        let last_mandatory = self.header.iter().rposition(|line| {
+            line.0 != "END" && (is_mandatory_keyword(&line.0) || line.0 == "TFIELDS")
+        });
+        if let Some(last_mandatory) = last_mandatory {
+            if anchor_position + offset <= last_mandatory {
+                return Err(self.invalid_keyword(anchor));
+            }
         }
+        let comment = match self.position(key) {
+            Some(i) => self.header_mut().remove(i).1.and_then(|line| line.comment),
+            None => None,
+        };
+        let i = self.existing_position(anchor)? + offset;
+        let value = Some(value);
+        self.header_mut()
+            .insert(i, (header_key, Some(HeaderValueComment { value, comment })));
+        Ok(())
     }
-}
 
-impl CardImage {
-    fn new() -> CardImage {
-        CardImage([0u8; 80])
+    /// Set the comment of the card of keyword `key`. Fail if `key` is
+    /// missing or if `comment` has other characters than printable ASCII.
+    ///
+    /// The comment is truncated when written if it does not fit in the card
+    /// image, unless the card holds a long string.
+    pub fn set_comment(&mut self, key: &str, comment: &str) -> Result<(), FitsError> {
+        let i = self.existing_position(key)?;
+        self.check_text(key, comment)?;
+        let comment = Some(String::from(comment));
+        let line = &mut self.header_mut()[i];
+        match line.1 {
+            Some(ref mut value_comment) => value_comment.comment = comment,
+            None => {
+                line.1 = Some(HeaderValueComment {
+                    value: None,
+                    comment,
+                })
+            }
+        }
+        Ok(())
     }
 
-    fn to_header_key_value(&self) -> Option<(HeaderKeyWord, Option<HeaderValueComment>)> {
-        let card = self.0;
-        let keyword = &card[0..8];
-        let value_indicator = &card[8..10];
-        let value_comment = &card[10..80];
-        let mut key = HeaderKeyWord::new();
-        for c in keyword {
-            if *c == SPACE_U8 {
-                break;
+    /// Rename keyword `from` to `to`, keeping its value and comment.
+    ///
+    /// Fail if `from` is missing, if `to` is not a valid keyword or is
+    /// already present, or if either is a mandatory keyword such as `BITPIX`
+    /// or `NAXISn`, which are set from the data.
+    pub fn rename(&mut self, from: &str, to: &str) -> Result<(), FitsError> {
+        let i = self.existing_position(from)?;
+        let header_key = self.header_key(to)?;
+        for key in &[from, to] {
+            if is_mandatory_keyword(key) {
+                return Err(self.invalid_keyword(key));
             }
-            key.push(*c as char);
-        }
-        if key.is_empty() {
-            return None;
         }
-        if value_indicator[0] == EQUAL_U8 && value_indicator[1] == SPACE_U8 {
-            let val = HeaderValueComment::new(value_comment);
-            Some((key, Some(val)))
-        } else {
-            Some((key, None))
+        if self.position(to).is_some() {
+            return Err(self.invalid_keyword(to));
         }
+        self.header_mut()[i].0 = header_key;
+        Ok(())
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::{CardImage, Fits, FitsData, HeaderValue};
-
-    impl CardImage {
-        fn from(s: &str) -> CardImage {
-            let mut card = [' ' as u8; 80];
-            for (i, c) in s.chars().enumerate() {
-                card[i] = c as u8;
-            }
-            CardImage(card)
+    /// Remove the first card of keyword `key` and return its value.
+    ///
+    /// Fail if `key` is missing or is a mandatory keyword.
+    pub fn remove(&mut self, key: &str) -> Result<Option<HeaderValue>, FitsError> {
+        let i = self.existing_position(key)?;
+        if is_mandatory_keyword(key) {
+            return Err(self.invalid_keyword(key));
         }
+        Ok(self.header_mut().remove(i).1.and_then(|line| line.value))
     }
 
-    #[test]
-    fn read_card_image_character_string() {
-        let card = CardImage::from("AUTHOR  = 'Malik Olivier Boussejra <malik@boussejra.com>' /");
-        let header_key_value = card.to_header_key_value().unwrap();
-        assert_eq!(header_key_value.0, String::from("AUTHOR"));
-        let value_comment = header_key_value.1.unwrap();
-        assert_eq!(
-            value_comment.value,
-            Some(HeaderValue::CharacterString(String::from(
-                "Malik Olivier Boussejra <malik@boussejra.com>",
-            )))
-        );
-        assert_eq!(value_comment.comment, Some(String::from("")));
+    /// Append `COMMENT` cards with `text`, split over several cards if it
+    /// does not fit in one. Fail if `text` has other characters than
+    /// printable ASCII.
+    pub fn add_comment(&mut self, text: &str) -> Result<(), FitsError> {
+        self.add_commentary("COMMENT", text)
     }
 
-    #[test]
-    fn read_card_image_no_comment() {
-        let card = CardImage::from("AUTHOR  = ''");
-        let header_key_value = card.to_header_key_value().unwrap();
-        let value_comment = header_key_value.1.unwrap();
-        assert_eq!(
-            value_comment.value,
-            Some(HeaderValue::CharacterString(String::from("")))
-        );
-        assert_eq!(value_comment.comment, None);
+    /// Append `HISTORY` cards with `text`, split over several cards if it
+    /// does not fit in one. Fail if `text` has other characters than
+    /// printable ASCII.
+    pub fn add_history(&mut self, text: &str) -> Result<(), FitsError> {
+        self.add_commentary("HISTORY", text)
     }
 
-    #[test]
-    fn read_card_image_character_trailing_space() {
-        let card = CardImage::from("AUTHOR  = '  ab d  '");
-        let header_key_value = card.to_header_key_value().unwrap();
-        let value_comment = header_key_value.1.unwrap();
-        assert_eq!(
-            value_comment.value,
-            Some(HeaderValue::CharacterString(String::from("  ab d")))
-        );
+    fn add_commentary(&mut self, key: &str, text: &str) -> Result<(), FitsError> {
+        self.check_text(key, text)?;
+        // Text fills columns 9 to 80
+        const TEXT_LENGTH: usize = 80 - 8;
+        let chars: Vec<char> = text.chars().collect();
+        for chunk in chars.chunks(TEXT_LENGTH) {
+            let comment = Some(chunk.iter().collect());
+            self.push_line((
+                String::from(key),
+                Some(HeaderValueComment {
+                    value: None,
+                    comment,
+                }),
+            ));
+        }
+        Ok(())
     }
 
-    #[test]
-    fn read_card_image_character_blank() {
-        let card = CardImage::from("AUTHOR  = '  '");
-        let header_key_value = card.to_header_key_value().unwrap();
-        let value_comment = header_key_value.1.unwrap();
+    /// Tree formed by the `HIERARCH` keywords of the header, in header
+    /// order.
+    ///
+    /// For example, `HIERARCH ESO DET CHIP TEMP` and `HIERARCH ESO DET DIT`
+    /// give a single root node `ESO` with a child `DET`, whose children are
+    /// the leaves `CHIP TEMP` (as `CHIP` then `TEMP`) and `DIT`.
+    pub fn hierarch(&self) -> Vec<HierarchNode<'_>> {
+        let mut roots = Vec::new();
+        for line in self.header.iter() {
+            if !line.0.starts_with(HIERARCH_PREFIX) {
+                continue;
+            }
+            let value = line
+                .1
+                .as_ref()
+                .and_then(|value_comment| value_comment.value.as_ref());
+            let mut nodes = &mut roots;
+            let mut names = line.0[HIERARCH_PREFIX.len()..].split(' ').peekable();
+            while let Some(name) = names.next() {
+                let i = match nodes
+                    .iter()
+                    .position(|node: &HierarchNode| node.name == name)
+                {
+                    Some(i) => i,
+                    None => {
+                        nodes.push(HierarchNode {
+                            name,
+                            value: None,
+                            children: Vec::new(),
+                        });
+                        nodes.len() - 1
+                    }
+                };
+                if names.peek().is_none() {
+                    nodes[i].value = value;
+                }
+                nodes = &mut nodes[i].children;
+            }
+        }
+        roots
+    }
+
+    /// Get value by key, converted to type `T`.
+    ///
+    /// Return an error if the key is missing, has no value or if the value
+    /// cannot be converted. See [`FromHeaderValue`] for the available types.
+    ///
+    /// ```rust,no_run
+    /// # use fitrs::Fits;
+    /// let fits = Fits::open("path/to/fits/file.fits").expect("Failed to open");
+    /// let exptime = fits[0].get::<f64>("EXPTIME").expect("No exposure time");
+    /// let object: String = fits[0].get("OBJECT").expect("No object");
+    /// ```
+    pub fn get<T: FromHeaderValue>(&self, key: &str) -> Result<T, FitsError> {
+        let value = self.value(key).ok_or_else(|| self.missing_keyword(key))?;
+        T::from_header_value(value).ok_or_else(|| FitsError::UnexpectedType {
+            hdu: self.index,
+            keyword: String::from(key),
+            expected: T::EXPECTED,
+            value: value.clone(),
+        })
+    }
+
+    pub(crate) fn value_as_integer_number(&self, key: &str) -> Option<i64> {
+        self.value(key).and_then(|val| match val {
+            &HeaderValue::IntegerNumber(n) => Some(n),
+            _ => None,
+        })
+    }
+
+    fn value_as_real_number(&self, key: &str) -> Option<f64> {
+        self.value(key).and_then(|val| match *val {
+            HeaderValue::IntegerNumber(n) => Some(n as f64),
+            HeaderValue::RealFloatingNumber(n) => Some(n),
+            _ => None,
+        })
+    }
+
+    /// Whether integers are stored as unsigned, with `BSCALE = 1` and
+    /// `BZERO = 2^(BITPIX - 1)`.
+    fn is_unsigned_convention(&self, bitpix: i64) -> bool {
+        self.value_as_real_number("BSCALE").unwrap_or(1.0) == 1.0
+            && self.value_as_real_number("BZERO") == Some(2f64.powi(bitpix as i32 - 1))
+    }
+
+    /// Get integer value of a keyword required to read the [`Hdu`].
+    pub(crate) fn integer_keyword(&self, key: &str) -> Result<i64, FitsError> {
+        match self.value(key) {
+            Some(&HeaderValue::IntegerNumber(n)) => Ok(n),
+            Some(_) => Err(self.invalid_keyword(key)),
+            None => Err(self.missing_keyword(key)),
+        }
+    }
+
+    /// Value of a keyword giving a size or a count, which must be a
+    /// non-negative integer.
+    pub(crate) fn size_keyword(&self, key: &str) -> Result<usize, FitsError> {
+        let n = self.integer_keyword(key)?;
+        usize::try_from(n).map_err(|_| self.invalid_keyword(key))
+    }
+
+    /// Same as [`Hdu::size_keyword`], with `default` if the keyword is
+    /// missing.
+    pub(crate) fn optional_size_keyword(
+        &self,
+        key: &str,
+        default: usize,
+    ) -> Result<usize, FitsError> {
+        match self.size_keyword(key) {
+            Err(FitsError::MissingKeyword { .. }) => Ok(default),
+            result => result,
+        }
+    }
+
+    /// Position of the [`Hdu`] in file, [`None`] if not written to a file
+    /// yet.
+    pub(crate) fn hdu_index(&self) -> Option<usize> {
+        self.index
+    }
+
+    pub(crate) fn missing_keyword(&self, key: &str) -> FitsError {
+        FitsError::MissingKeyword {
+            hdu: self.index,
+            keyword: String::from(key),
+        }
+    }
+
+    /// Key as stored in the header for a card written for `key`, or an
+    /// error if `key` is not a valid keyword.
+    fn header_key(&self, key: &str) -> Result<String, FitsError> {
+        normalize_key(key).ok_or_else(|| FitsError::InvalidKeywordName {
+            hdu: self.index,
+            keyword: String::from(key),
+        })
+    }
+
+    /// Key as stored in the header for a card inserted for `key` with
+    /// `value`, or an error if such a card cannot be inserted.
+    fn insertion_key(&self, key: &str, value: &HeaderValue) -> Result<String, FitsError> {
+        let header_key = self.header_key(key)?;
+        if is_mandatory_keyword(&header_key) && (header_key != "XTENSION" || self.index.is_some()) {
+            return Err(self.invalid_keyword(key));
+        }
+        if let HeaderValue::CharacterString(ref text) = *value {
+            self.check_text(key, text)?;
+        }
+        Ok(header_key)
+    }
+
+    /// Fail if `text`, written in the card of keyword `key`, has other
+    /// characters than printable ASCII.
+    fn check_text(&self, key: &str, text: &str) -> Result<(), FitsError> {
+        if text.bytes().all(|c| (b' '..=b'~').contains(&c)) {
+            Ok(())
+        } else {
+            Err(FitsError::InvalidText {
+                hdu: self.index,
+                keyword: String::from(key),
+            })
+        }
+    }
+
+    pub(crate) fn invalid_keyword(&self, key: &str) -> FitsError {
+        FitsError::InvalidKeyword {
+            hdu: self.index,
+            keyword: String::from(key),
+        }
+    }
+
+    fn naxis(&self) -> Result<Vec<usize>, FitsError> {
+        let naxis = self.integer_keyword("NAXIS")?;
+        if !(0..=999).contains(&naxis) {
+            return Err(self.invalid_keyword("NAXIS"));
+        }
+        let mut vec = Vec::new();
+        for i in 1..(naxis + 1) {
+            let mut key = String::from("NAXIS");
+            key.push_str(&i.to_string());
+            vec.push(self.size_keyword(&key)?);
+        }
+        Ok(vec)
+    }
+
+    /// Number of elements in the data unit, including the parameters of
+    /// random groups and the heap of binary tables:
+    /// `GCOUNT * (PCOUNT + NAXIS1 * NAXIS2 * ... * NAXISm)`.
+    ///
+    /// For random groups, `NAXIS1` is 0 and is left out of the product.
+    ///
+    /// Fail if the length does not fit in 64 bits.
+    fn data_length(&self) -> Result<u64, FitsError> {
+        let naxis = self.naxis()?;
+        let random_groups = match self.value("GROUPS") {
+            Some(&HeaderValue::Logical(groups)) => groups && naxis.first() == Some(&0),
+            _ => false,
+        };
+        let axes = if random_groups {
+            &naxis[1..]
+        } else {
+            &naxis[..]
+        };
+        let overflow = || self.invalid_keyword("NAXIS");
+        let len = if axes.is_empty() {
+            0
+        } else {
+            axes.iter()
+                .try_fold(1u64, |len, &n| len.checked_mul(n as u64))
+                .ok_or_else(overflow)?
+        };
+        let pcount = self.optional_size_keyword("PCOUNT", 0)? as u64;
+        let gcount = self.optional_size_keyword("GCOUNT", 1)? as u64;
+        pcount
+            .checked_add(len)
+            .and_then(|len| len.checked_mul(gcount))
+            .ok_or_else(overflow)
+    }
+
+    /// Whether the data unit is only made of the data array: the primary
+    /// array or an `IMAGE` extension, without random groups.
+    fn is_image(&self) -> Result<bool, FitsError> {
+        let image = match self.value("XTENSION") {
+            None => true,
+            Some(HeaderValue::CharacterString(xtension)) => xtension.trim_end() == "IMAGE",
+            Some(_) => false,
+        };
+        let groups = matches!(self.value("GROUPS"), Some(&HeaderValue::Logical(true)));
+        Ok(image
+            && !groups
+            && self.optional_size_keyword("PCOUNT", 0)? == 0
+            && self.optional_size_keyword("GCOUNT", 1)? == 1)
+    }
+
+    pub(crate) fn data_byte_length(&self) -> Result<u64, FitsError> {
+        self.data_length()?
+            .checked_mul(self.bitpix_byte_size()? as u64)
+            .ok_or_else(|| self.invalid_keyword("NAXIS"))
+    }
+
+    /// Position in file where the next [`Hdu`] starts, at the end of the
+    /// padded data unit.
+    fn next_hdu_position(&self) -> u64 {
+        let len = self.data_byte_length().unwrap_or(0);
+        self.data_start + padded_length(len)
+    }
+
+    fn is_data_cached(&self) -> bool {
+        self.data.read().unwrap().is_some()
+    }
+
+    fn data(&self) -> Option<&FitsData> {
+        if let Some(ref data) = *self.data.read().unwrap() {
+            let data = data as *const FitsData;
+            Some(unsafe { &*data })
+        } else {
+            None
+        }
+    }
+
+    /// Get data array stored in the [`Hdu`].
+    ///
+    /// Panic if the data cannot be read. Use [`Hdu::try_read_data`] to get an
+    /// error instead.
+    pub fn read_data(&self) -> &FitsData {
+        self.try_read_data().unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Get data array stored in the [`Hdu`], or an error if the data cannot
+    /// be read.
+    pub fn try_read_data(&self) -> Result<&FitsData, FitsError> {
+        if self.is_data_cached() {
+            Ok(self.data().unwrap())
+        } else {
+            self.read_data_force()
+        }
+    }
+
+    /// Get mutable data array stored in the [`Hdu`], to be written back to
+    /// file with [`Fits::flush`].
+    ///
+    /// Panic if the data cannot be read, or if the [`Hdu`] is not an image.
+    /// Use [`Hdu::try_read_data_mut`] to get an error instead.
+    pub fn read_data_mut(&mut self) -> &mut FitsData {
+        self.try_read_data_mut().unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Get mutable data array stored in the [`Hdu`], or an error if the data
+    /// cannot be read. See [`Hdu::read_data_mut`].
+    ///
+    /// The shape of the array and its variant must be kept, as they are
+    /// given by `NAXISn` and `BITPIX`. Undefined values need `BLANK`.
+    ///
+    /// Fail if the [`Hdu`] holds a table or random groups, whose data unit
+    /// is not only made of the data array.
+    pub fn try_read_data_mut(&mut self) -> Result<&mut FitsData, FitsError> {
+        if !self.is_image()? {
+            return Err(FitsError::NotAnImage { hdu: self.index });
+        }
+        self.try_read_data()?;
+        self.data_changed = true;
+        Ok(self.data.get_mut().unwrap().as_mut().unwrap())
+    }
+
+    /// Read the header of the HDU number `index`, starting at `position` in
+    /// file, from `reader` already at `position`. The [`Hdu`] is not
+    /// attached to a file.
+    ///
+    /// Return [`None`] at the end of the file.
+    pub(crate) fn read_header<R: Read + ?Sized>(
+        reader: &mut R,
+        index: usize,
+        position: u64,
+    ) -> Result<Option<Hdu>, FitsError> {
+        let mut line = CardImage::new();
+        let mut line_count = 0;
+        let mut cards = Vec::new();
+        let mut end = false;
+        while (line_count % 36) != 0 || !end {
+            match reader.read_exact(&mut line.0) {
+                Ok(_) => {
+                    if !end {
+                        let (key, val) = line.to_header_key_value();
+                        if key == "END" {
+                            end = true;
+                        } else {
+                            cards.push(((key, val), CardImage(line.0)));
+                        }
+                    }
+                }
+                Err(ref e) if e.kind() == ErrorKind::UnexpectedEof && line_count == 0 => {
+                    return Ok(None)
+                }
+                Err(ref e) if e.kind() == ErrorKind::UnexpectedEof => {
+                    return Err(FitsError::TruncatedHeader {
+                        hdu: index,
+                        offset: position,
+                    })
+                }
+                Err(error) => {
+                    return Err(FitsError::Io {
+                        offset: Some(position + line_count * 80),
+                        error,
+                    })
+                }
+            };
+            line_count += 1;
+        }
+        let original = merge_long_strings(cards);
+        let hdu = Hdu {
+            header: original.iter().map(|line| line.line.clone()).collect(),
+            header_changed: false,
+            original,
+            data_changed: false,
+            header_start: position,
+            data_start: position + line_count * 80,
+            index: Some(index),
+            file: None,
+            mapping: None,
+            data: RwLock::new(None),
+        };
+        // Check that the size of the data unit is known
+        hdu.data_byte_length()?;
+        Ok(Some(hdu))
+    }
+
+    /// Position in file of the start of the header.
+    pub(crate) fn header_start(&self) -> u64 {
+        self.header_start
+    }
+
+    /// Length in bytes of the header, including its padding.
+    pub(crate) fn header_byte_length(&self) -> u64 {
+        self.data_start - self.header_start
+    }
+
+    /// Decode the data array from `reader`, at the start of the data unit,
+    /// and cache it.
+    pub(crate) fn read_data_from<R: Read>(
+        &mut self,
+        reader: &mut R,
+    ) -> Result<&FitsData, FitsError> {
+        let naxis = self.naxis()?;
+        let data = self.decode_data(&naxis, reader)?;
+        Ok(self.data.get_mut().unwrap().insert(data))
+    }
+
+    /// Check that the cached data array has the type given by `BITPIX` and
+    /// the shape given by `NAXISn`, and that `BLANK` is defined if it has
+    /// undefined values.
+    fn check_data(&self) -> Result<(), FitsError> {
+        let bitpix = self.integer_keyword("BITPIX")?;
+        let naxis = self.naxis()?;
+        // Stored values, before BZERO and BSCALE, must fit in BITPIX
+        let (min, max) = match bitpix {
+            8 => (0, i64::from(u8::MAX)),
+            16 => (i64::from(i16::MIN), i64::from(i16::MAX)),
+            32 => (i64::from(i32::MIN), i64::from(i32::MAX)),
+            _ => (i64::MIN, i64::MAX),
+        };
+        if let Some(data) = self.data() {
+            if data.has_undefined() {
+                match self.value_as_integer_number("BLANK") {
+                    None => return Err(self.missing_keyword("BLANK")),
+                    Some(blank) if blank < min || blank > max => {
+                        return Err(self.invalid_keyword("BLANK"))
+                    }
+                    Some(_) => (),
+                }
+            }
+        }
+        let matches = match self.data() {
+            Some(data) => {
+                let (shape, len) = data.shape_and_len();
+                let type_matches = match (bitpix, data) {
+                    (16, FitsData::IntegersI32(array)) | (32, FitsData::IntegersI32(array)) => {
+                        array
+                            .data
+                            .iter()
+                            .flatten()
+                            .all(|&n| i64::from(n) >= min && i64::from(n) <= max)
+                    }
+                    (8, FitsData::IntegersU8(_))
+                    | (16, FitsData::IntegersU16(_))
+                    | (32, FitsData::IntegersU32(_))
+                    | (64, FitsData::IntegersI64(_))
+                    | (64, FitsData::IntegersU64(_))
+                    | (-32, FitsData::FloatingPoint32(_))
+                    | (-64, FitsData::FloatingPoint64(_)) => true,
+                    _ => false,
+                };
+                type_matches && shape == &naxis[..] && len == naxis.iter().product()
+            }
+            None => true,
+        };
+        if matches {
+            Ok(())
+        } else {
+            Err(FitsError::DataMismatch { hdu: self.index })
+        }
+    }
+
+    fn read_data_force(&self) -> Result<&FitsData, FitsError> {
+        let naxis = self.naxis()?;
+        let data = {
+            let mut file_lock = self.lock_file_at(self.data_start)?;
+            self.decode_data(&naxis, &mut *file_lock)?
+        };
+        let mut out = self.data.write().unwrap();
+        *out = Some(data);
+        // Release write-lock to be able to read and return back the data
+        drop(out);
+        Ok(self.data().unwrap())
+    }
+
+    /// Read a rectangular section of the data array, without reading the
+    /// whole array.
+    ///
+    /// For each axis, the section goes from `start` (included) to `end`
+    /// (excluded), taking one element every `step`. The shape of the
+    /// returned array is the number of elements taken on each axis.
+    ///
+    /// Panic if the slices do not have one element per axis, if a range is
+    /// out of the array, or if a step is 0. Use [`Hdu::try_read_section`] to
+    /// get an error instead.
+    ///
+    /// ```no_run
+    /// use fitrs::Fits;
+    ///
+    /// let fits = Fits::open("mosaic.fits").unwrap();
+    /// // 100x100 cutout starting at pixel (8000, 8000)
+    /// let cutout = fits[0].read_section(&[8000, 8000], &[8100, 8100], &[1, 1]);
+    /// ```
+    pub fn read_section(&self, start: &[usize], end: &[usize], step: &[usize]) -> FitsData {
+        self.try_read_section(start, end, step)
+            .unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Read a rectangular section of the data array, or an error if the
+    /// section is invalid or cannot be read. See [`Hdu::read_section`].
+    pub fn try_read_section(
+        &self,
+        start: &[usize],
+        end: &[usize],
+        step: &[usize],
+    ) -> Result<FitsData, FitsError> {
+        let naxis = self.naxis()?;
+        if start.len() != naxis.len() || end.len() != naxis.len() || step.len() != naxis.len() {
+            return Err(FitsError::InvalidSection(String::from(
+                "Section must have one range per axis",
+            )));
+        }
+        for (i, &n) in naxis.iter().enumerate() {
+            if start[i] > end[i] || end[i] > n || step[i] == 0 {
+                return Err(FitsError::InvalidSection(format!(
+                    "Invalid section for axis {}",
+                    i + 1
+                )));
+            }
+        }
+        let shape: Vec<usize> = (0..naxis.len())
+            .map(|i| (end[i] - start[i]).div_ceil(step[i]))
+            .collect();
+        let rows = section_rows(&naxis, &shape, start, end, step);
+        // The cached data may have been edited, or not be written yet
+        if let Some(data) = self.data() {
+            return Ok(data.section(&shape, &rows, step[0]));
+        }
+        let element_size = self.bitpix_byte_size()?;
+        let mut buf = Vec::with_capacity(shape.iter().product::<usize>() * element_size);
+        if !rows.is_empty() {
+            let row_length = ((shape[0] - 1) * step[0] + 1) * element_size;
+            let mut row_buf = vec![0u8; row_length];
+            // Read rows straight from memory if the file is mapped
+            let mapped = self.mapped_data()?;
+            let mut file_lock = match mapped {
+                Some(_) => None,
+                None => Some(self.lock_file_at(self.data_start)?),
+            };
+            for &offset in &rows {
+                let row = match (mapped, file_lock.as_mut()) {
+                    (Some(bytes), _) => {
+                        &bytes[offset * element_size..offset * element_size + row_length]
+                    }
+                    (None, Some(file_lock)) => {
+                        let position = self.data_start + (offset * element_size) as u64;
+                        file_lock
+                            .seek(SeekFrom::Start(position))
+                            .and_then(|_| file_lock.read_exact(&mut row_buf))
+                            .map_err(|error| FitsError::Io {
+                                offset: Some(position),
+                                error,
+                            })?;
+                        &row_buf[..]
+                    }
+                    (None, None) => unreachable!(),
+                };
+                for element in row.chunks(element_size).step_by(step[0]) {
+                    buf.extend_from_slice(element);
+                }
+            }
+        }
+        self.decode_data(&shape, &mut &buf[..])
+    }
+
+    /// Data unit without its padding, borrowed from the memory map of the
+    /// file. Return [`None`] if the file is not mapped.
+    fn mapped_data(&self) -> Result<Option<&[u8]>, FitsError> {
+        let bytes = match self.mapping {
+            Some(ref mapping) => (**mapping).as_ref(),
+            None => return Ok(None),
+        };
+        let end = self.data_start + self.data_byte_length()?;
+        if end > bytes.len() as u64 {
+            return Err(FitsError::Io {
+                offset: Some(self.data_start),
+                error: Error::new(ErrorKind::UnexpectedEof, "Data unit ends after end of file"),
+            });
+        }
+        Ok(Some(&bytes[self.data_start as usize..end as usize]))
+    }
+
+    /// Get the raw big-endian data unit, without its padding, borrowed from
+    /// the memory map of a file opened with [`Fits::open_mmap`].
+    ///
+    /// Return [`None`] if the file is not mapped. Panic if the data unit
+    /// goes past the end of the file. Use [`Hdu::try_raw_data`] to get an
+    /// error instead.
+    #[cfg(feature = "mmap")]
+    pub fn raw_data(&self) -> Option<&[u8]> {
+        self.try_raw_data().unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Get the raw big-endian data unit, or an error if it goes past the end
+    /// of the file. See [`Hdu::raw_data`].
+    #[cfg(feature = "mmap")]
+    pub fn try_raw_data(&self) -> Result<Option<&[u8]>, FitsError> {
+        self.mapped_data()
+    }
+
+    /// Get a typed view of the data array of a file opened with
+    /// [`Fits::open_mmap`], decoding each element only when it is accessed.
+    ///
+    /// `T` must match `BITPIX`: [`u8`] for 8, [`i16`] for 16, [`i32`] for
+    /// 32, [`i64`] for 64, [`f32`] for -32 and [`f64`] for -64. Elements are
+    /// the stored values: `BZERO`, `BSCALE` and `BLANK` are not applied.
+    ///
+    /// Return [`None`] if the file is not mapped. Panic if `T` does not match
+    /// `BITPIX` or if the data cannot be read. Use [`Hdu::try_view`] to get
+    /// an error instead.
+    #[cfg(feature = "mmap")]
+    pub fn view<T: RawElement>(&self) -> Option<DataView<'_, T>> {
+        self.try_view().unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Get a typed view of the data array, or an error if `T` does not match
+    /// `BITPIX` or if the data cannot be read. See [`Hdu::view`].
+    #[cfg(feature = "mmap")]
+    pub fn try_view<T: RawElement>(&self) -> Result<Option<DataView<'_, T>>, FitsError> {
+        if self.integer_keyword("BITPIX")? != T::BITPIX {
+            return Err(FitsError::DataMismatch { hdu: self.index });
+        }
+        let naxis = self.naxis()?;
+        Ok(self.mapped_data()?.map(|bytes| DataView::new(bytes, naxis)))
+    }
+
+    /// Decode an array of provided `shape` from raw big-endian data, as
+    /// defined by `BITPIX`, `BLANK` and `BZERO`.
+    fn decode_data(&self, shape: &[usize], reader: &mut dyn Read) -> Result<FitsData, FitsError> {
+        let bitpix = self.integer_keyword("BITPIX")?;
+        let io_error = |error| FitsError::Io {
+            offset: Some(self.data_start),
+            error,
+        };
+        let data = match bitpix {
+            8 => {
+                let blank = self.value_as_integer_number("BLANK");
+                FitsData::IntegersU8(
+                    Self::read_array(shape, reader, |file, len| {
+                        let mut buf = vec![0u8; len];
+                        file.read_exact(&mut buf)?;
+                        Ok(buf
+                            .into_iter()
+                            .map(|n| {
+                                if Some(n as i64) == blank {
+                                    None
+                                } else {
+                                    Some(n)
+                                }
+                            })
+                            .collect())
+                    })
+                    .map_err(io_error)?,
+                )
+            }
+            16 if self.is_unsigned_convention(bitpix) => {
+                let blank = self.value_as_integer_number("BLANK");
+                FitsData::IntegersU16(
+                    Self::read_array(shape, reader, |file, len| {
+                        let mut buf = vec![0i16; len];
+                        file.read_i16_into::<BigEndian>(&mut buf)?;
+                        Ok(buf
+                            .into_iter()
+                            .map(|n| {
+                                if Some(n as i64) == blank {
+                                    None
+                                } else {
+                                    Some(n as u16 ^ 0x8000)
+                                }
+                            })
+                            .collect())
+                    })
+                    .map_err(io_error)?,
+                )
+            }
+            16 => {
+                let blank = self.value_as_integer_number("BLANK");
+                FitsData::IntegersI32(
+                    Self::read_array(shape, reader, |file, len| {
+                        let mut buf = vec![0i16; len];
+                        file.read_i16_into::<BigEndian>(&mut buf)?;
+                        if let Some(blank) = blank {
+                            let blank = blank as i16;
+                            Ok(buf
+                                .into_iter()
+                                .map(|n| if n == blank { None } else { Some(n as i32) })
+                                .collect())
+                        } else {
+                            Ok(buf.into_iter().map(|n| Some(n as i32)).collect())
+                        }
+                    })
+                    .map_err(io_error)?,
+                )
+            }
+            32 if self.is_unsigned_convention(bitpix) => {
+                let blank = self.value_as_integer_number("BLANK");
+                FitsData::IntegersU32(
+                    Self::read_array(shape, reader, |file, len| {
+                        let mut buf = vec![0i32; len];
+                        file.read_i32_into::<BigEndian>(&mut buf)?;
+                        Ok(buf
+                            .into_iter()
+                            .map(|n| {
+                                if Some(n as i64) == blank {
+                                    None
+                                } else {
+                                    Some(n as u32 ^ 0x8000_0000)
+                                }
+                            })
+                            .collect())
+                    })
+                    .map_err(io_error)?,
+                )
+            }
+            32 => {
+                let blank = self.value_as_integer_number("BLANK");
+                FitsData::IntegersI32(
+                    Self::read_array(shape, reader, |file, len| {
+                        let mut buf = vec![0i32; len];
+                        file.read_i32_into::<BigEndian>(&mut buf)?;
+                        if let Some(blank) = blank {
+                            Ok(buf
+                                .into_iter()
+                                .map(|n| if n as i64 == blank { None } else { Some(n) })
+                                .collect())
+                        } else {
+                            Ok(buf.into_iter().map(Some).collect())
+                        }
+                    })
+                    .map_err(io_error)?,
+                )
+            }
+            64 if self.is_unsigned_convention(bitpix) => {
+                let blank = self.value_as_integer_number("BLANK");
+                FitsData::IntegersU64(
+                    Self::read_array(shape, reader, |file, len| {
+                        let mut buf = vec![0i64; len];
+                        file.read_i64_into::<BigEndian>(&mut buf)?;
+                        Ok(buf
+                            .into_iter()
+                            .map(|n| {
+                                if Some(n) == blank {
+                                    None
+                                } else {
+                                    Some(n as u64 ^ 0x8000_0000_0000_0000)
+                                }
+                            })
+                            .collect())
+                    })
+                    .map_err(io_error)?,
+                )
+            }
+            64 => {
+                let blank = self.value_as_integer_number("BLANK");
+                FitsData::IntegersI64(
+                    Self::read_array(shape, reader, |file, len| {
+                        let mut buf = vec![0i64; len];
+                        file.read_i64_into::<BigEndian>(&mut buf)?;
+                        Ok(buf
+                            .into_iter()
+                            .map(|n| if Some(n) == blank { None } else { Some(n) })
+                            .collect())
+                    })
+                    .map_err(io_error)?,
+                )
+            }
+            -32 => FitsData::FloatingPoint32(
+                Self::read_array(shape, reader, |file, len| {
+                    let mut buf = vec![0f32; len];
+                    file.read_f32_into::<BigEndian>(&mut buf)?;
+                    Ok(buf)
+                })
+                .map_err(io_error)?,
+            ),
+            -64 => FitsData::FloatingPoint64(
+                Self::read_array(shape, reader, |file, len| {
+                    let mut buf = vec![0f64; len];
+                    file.read_f64_into::<BigEndian>(&mut buf)?;
+                    Ok(buf)
+                })
+                .map_err(io_error)?,
+            ),
+            _ => {
+                return Err(FitsError::UnsupportedBitpix {
+                    hdu: self.index,
+                    bitpix,
+                })
+            }
+        };
+        Ok(data)
+    }
+
+    /// Get physical values of the data array stored in the [`Hdu`].
+    ///
+    /// Stored values are scaled as `BZERO + BSCALE * value`, as defined in
+    /// [FITS standard 5.3.2](https://archive.stsci.edu/fits/fits_standard/node34.html#SECTION00932000000000000000).
+    /// Integers are converted to [`FitsData::FloatingPoint32`] for
+    /// `BITPIX = 8` and `BITPIX = 16`, or to [`FitsData::FloatingPoint64`]
+    /// otherwise, with undefined values as `NaN`.
+    ///
+    /// Return a copy of [`Hdu::read_data`] if `BSCALE` and `BZERO` are absent,
+    /// or if they follow the unsigned integer convention. Panic if the data
+    /// cannot be read.
+    pub fn read_physical_data(&self) -> FitsData {
+        self.try_read_physical_data()
+            .unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Get physical values of the data array stored in the [`Hdu`], or an
+    /// error if the data cannot be read. See [`Hdu::read_physical_data`].
+    pub fn try_read_physical_data(&self) -> Result<FitsData, FitsError> {
+        let bscale = self.value_as_real_number("BSCALE").unwrap_or(1.0);
+        let bzero = self.value_as_real_number("BZERO").unwrap_or(0.0);
+        let data = self.try_read_data()?;
+        if bscale == 1.0 && bzero == 0.0 {
+            return Ok(data.clone());
+        }
+        let scale = |n: f64| bzero + bscale * n;
+        Ok(match data {
+            FitsData::IntegersU8(array) => FitsData::FloatingPoint32(
+                array.map(|n| n.map_or(f32::NAN, |n| scale(n as f64) as f32)),
+            ),
+            FitsData::IntegersI32(array) => {
+                let scale = |n: &Option<i32>| n.map_or(f64::NAN, |n| scale(n as f64));
+                if self.value_as_integer_number("BITPIX") == Some(16) {
+                    FitsData::FloatingPoint32(array.map(|n| scale(n) as f32))
+                } else {
+                    FitsData::FloatingPoint64(array.map(scale))
+                }
+            }
+            FitsData::IntegersI64(array) => {
+                FitsData::FloatingPoint64(array.map(|n| n.map_or(f64::NAN, |n| scale(n as f64))))
+            }
+            FitsData::IntegersU16(_) | FitsData::IntegersU32(_) | FitsData::IntegersU64(_) => {
+                data.clone()
+            }
+            FitsData::FloatingPoint32(array) => {
+                FitsData::FloatingPoint32(array.map(|n| scale(*n as f64) as f32))
+            }
+            FitsData::FloatingPoint64(array) => FitsData::FloatingPoint64(array.map(|n| scale(*n))),
+        })
+    }
+
+    /// Read the binary table stored in the [`Hdu`].
+    ///
+    /// Return [`None`] if the [`Hdu`] is not a `BINTABLE` extension. Panic if
+    /// the table cannot be read.
+    pub fn read_bintable(&self) -> Option<BinTable> {
+        self.try_read_bintable().unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Read the binary table stored in the [`Hdu`], or an error if the table
+    /// cannot be read. See [`Hdu::read_bintable`].
+    pub fn try_read_bintable(&self) -> Result<Option<BinTable>, FitsError> {
+        BinTable::new(self)
+    }
+
+    /// Read the ASCII table stored in the [`Hdu`].
+    ///
+    /// Return [`None`] if the [`Hdu`] is not a `TABLE` extension. Panic if
+    /// the table cannot be read.
+    pub fn read_ascii_table(&self) -> Option<AsciiTable> {
+        self.try_read_ascii_table()
+            .unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Read the ASCII table stored in the [`Hdu`], or an error if the table
+    /// cannot be read. See [`Hdu::read_ascii_table`].
+    pub fn try_read_ascii_table(&self) -> Result<Option<AsciiTable>, FitsError> {
+        AsciiTable::new(self)
+    }
+
+    /// Read the first `len` bytes of the data unit as is.
+    pub(crate) fn read_raw_data(&self, len: usize) -> Result<Vec<u8>, FitsError> {
+        let mut file_lock = self.lock_file_at(self.data_start)?;
+        let mut buf = vec![0u8; len];
+        file_lock
+            .read_exact(&mut buf)
+            .map_err(|error| FitsError::Io {
+                offset: Some(self.data_start),
+                error,
+            })?;
+        Ok(buf)
+    }
+
+    fn read_array<F, T>(
+        shape: &[usize],
+        reader: &mut dyn Read,
+        read: F,
+    ) -> Result<FitsDataArray<T>, Error>
+    where
+        F: Fn(&mut dyn Read, usize) -> Result<Vec<T>, Error>,
+    {
+        Ok(FitsDataArray::new(
+            shape,
+            read(reader, shape.iter().product())?,
+        ))
+    }
+
+    /// Lock the file backing the [`Hdu`] and move its cursor to `position`.
+    fn lock_file_at(&self, position: u64) -> Result<MutexGuard<'_, Box<dyn Storage>>, FitsError> {
+        let io_error = |error| FitsError::Io {
+            offset: Some(position),
+            error,
+        };
+        let file = self
+            .file
+            .as_ref()
+            .ok_or_else(|| io_error(Error::new(ErrorKind::NotFound, "Hdu is not in a file")))?;
+        let mut file_lock = file.lock().expect("Get file lock");
+        file_lock
+            .seek(SeekFrom::Start(position))
+            .map_err(io_error)?;
+        Ok(file_lock)
+    }
+
+    /// Size in bytes of one element of the data array.
+    fn bitpix_byte_size(&self) -> Result<usize, FitsError> {
+        match self.integer_keyword("BITPIX")? {
+            bitpix @ (8 | 16 | 32 | 64 | -32 | -64) => Ok(bitpix.unsigned_abs() as usize / 8),
+            bitpix => Err(FitsError::UnsupportedBitpix {
+                hdu: self.index,
+                bitpix,
+            }),
+        }
+    }
+
+    /// Get comment of the first card with provided key. Return [`None`] if
+    /// the key is not found or has no comment.
+    pub fn comment(&self, key: &str) -> Option<&str> {
+        self.line(key)
+            .and_then(|line| line.1.as_ref())
+            .and_then(|value_comment| value_comment.comment.as_deref())
+    }
+
+    /// Text of all `COMMENT` cards, in header order.
+    pub fn comments(&self) -> Vec<&str> {
+        self.commentary("COMMENT")
+    }
+
+    /// Text of all `HISTORY` cards, in header order.
+    pub fn history(&self) -> Vec<&str> {
+        self.commentary("HISTORY")
+    }
+
+    fn commentary(&self, key: &str) -> Vec<&str> {
+        self.cards()
+            .filter(|&(k, _, _)| k == key)
+            .map(|(_, _, comment)| comment.unwrap_or(""))
+            .collect()
+    }
+
+    /// Iterate over the cards of the header in order, except `END`.
+    ///
+    /// See [`HeaderIter`].
+    pub fn cards(&self) -> HeaderIter<'_> {
+        HeaderIter {
+            iter: self.header.iter(),
+        }
+    }
+
+    /// Header of the [`Hdu`] as written to file, with its mandatory keywords
+    /// in the order required by the standard for a primary HDU or for an
+    /// extension.
+    fn normalized_header(
+        &self,
+        primary: bool,
+    ) -> Result<Vec<(HeaderKeyWord, Option<HeaderValueComment>)>, Error> {
+        let bitpix = self
+            .value_as_integer_number("BITPIX")
+            .ok_or_else(|| invalid_data("BITPIX is missing"))?;
+        let naxis = self.naxis()?;
+        let mut mandatory = Vec::new();
+        if primary {
+            mandatory.push((String::from("SIMPLE"), HeaderValue::Logical(true)));
+        } else {
+            let xtension = match self.value("XTENSION") {
+                Some(HeaderValue::CharacterString(xtension)) => xtension.clone(),
+                _ => String::from("IMAGE"),
+            };
+            mandatory.push((
+                String::from("XTENSION"),
+                HeaderValue::CharacterString(xtension),
+            ));
+        }
+        mandatory.push((String::from("BITPIX"), HeaderValue::IntegerNumber(bitpix)));
+        mandatory.push((
+            String::from("NAXIS"),
+            HeaderValue::IntegerNumber(naxis.len() as i64),
+        ));
+        for (i, n) in naxis.iter().enumerate() {
+            mandatory.push((
+                format!("NAXIS{}", i + 1),
+                HeaderValue::IntegerNumber(*n as i64),
+            ));
+        }
+        for &(key, default) in &[("PCOUNT", 0), ("GCOUNT", 1)] {
+            // Random groups in the primary HDU have them too
+            if !primary || self.value(key).is_some() {
+                let count = self.value_as_integer_number(key).unwrap_or(default);
+                mandatory.push((String::from(key), HeaderValue::IntegerNumber(count)));
+            }
+        }
+        if !primary {
+            if let Some(tfields) = self.value("TFIELDS") {
+                mandatory.push((String::from("TFIELDS"), tfields.clone()));
+            }
+        }
+
+        let mut header = Vec::new();
+        for (key, value) in mandatory {
+            let comment = self.comment(&key).map(String::from);
+            let value = Some(value);
+            header.push((key, Some(HeaderValueComment { value, comment })));
+        }
+        let mandatory_count = header.len();
+        for line in &self.header {
+            let moved = header[..mandatory_count]
+                .iter()
+                .any(|(key, _)| *key == line.0);
+            if !moved && !is_mandatory_keyword(&line.0) {
+                header.push(line.clone());
+            }
+        }
+        Ok(header)
+    }
+
+    /// Write header of the [`Hdu`] padded to the end of the block. Return the
+    /// number of bytes written.
+    ///
+    /// Lines left unedited since the header was last read or written are
+    /// written back with their original card images.
+    fn write_header<W: Write>(&mut self, writer: &mut W) -> Result<u64, Error> {
+        let mut used = vec![false; self.original.len()];
+        let mut written = Vec::new();
+        for line in &self.header {
+            let original = self
+                .original
+                .iter()
+                .zip(used.iter_mut())
+                .find(|(original, used)| !**used && original.line == *line);
+            let cards = match original {
+                Some((original, used)) => {
+                    *used = true;
+                    original.cards.clone()
+                }
+                None => CardImage::from_line(line)?,
+            };
+            written.push(OriginalLine {
+                line: line.clone(),
+                cards,
+            });
+        }
+        let mut cards: Vec<_> = written
+            .iter()
+            .flat_map(|line| line.cards.iter().cloned())
+            .collect();
+        cards.push(CardImage::from_key_value("END", None, None)?);
+        while cards.len() % 36 != 0 {
+            cards.push(CardImage([SPACE_U8; 80]));
+        }
+        for card in &cards {
+            writer.write_all(&card.0)?;
+        }
+        self.original = written;
+        Ok(cards.len() as u64 * 80)
+    }
+
+    /// Write data array of the [`Hdu`] as big-endian values, as defined by
+    /// `BITPIX`. Pad the data with zeros to the end of the block.
+    fn write_data<W: Write>(&self, writer: &mut W) -> Result<(), Error> {
+        let bitpix = self
+            .value_as_integer_number("BITPIX")
+            .ok_or_else(|| invalid_data("BITPIX is missing"))?;
+        let blank = self.value_as_integer_number("BLANK");
+        let missing_blank = || invalid_data("Undefined value but BLANK is missing");
+        let out_of_range = || invalid_data("Value out of the range of BITPIX");
+        let mut writer = BufWriter::new(writer);
+        let len = match (bitpix, self.try_read_data()?) {
+            (8, FitsData::IntegersU8(array)) => {
+                for n in &array.data {
+                    let n = n.map(|n| n as i64).or(blank).ok_or_else(missing_blank)?;
+                    writer.write_u8(u8::try_from(n).map_err(|_| out_of_range())?)?;
+                }
+                array.data.len()
+            }
+            (16, FitsData::IntegersI32(array)) => {
+                for n in &array.data {
+                    let n = n.map(|n| n as i64).or(blank).ok_or_else(missing_blank)?;
+                    writer.write_i16::<BigEndian>(i16::try_from(n).map_err(|_| out_of_range())?)?;
+                }
+                array.data.len() * 2
+            }
+            (16, FitsData::IntegersU16(array)) => {
+                for n in &array.data {
+                    let n = n.map(|n| (n ^ 0x8000) as i16 as i64);
+                    let n = n.or(blank).ok_or_else(missing_blank)?;
+                    writer.write_i16::<BigEndian>(i16::try_from(n).map_err(|_| out_of_range())?)?;
+                }
+                array.data.len() * 2
+            }
+            (32, FitsData::IntegersU32(array)) => {
+                for n in &array.data {
+                    let n = n.map(|n| (n ^ 0x8000_0000) as i32 as i64);
+                    let n = n.or(blank).ok_or_else(missing_blank)?;
+                    writer.write_i32::<BigEndian>(i32::try_from(n).map_err(|_| out_of_range())?)?;
+                }
+                array.data.len() * 4
+            }
+            (32, FitsData::IntegersI32(array)) => {
+                for n in &array.data {
+                    let n = n.map(|n| n as i64).or(blank).ok_or_else(missing_blank)?;
+                    writer.write_i32::<BigEndian>(i32::try_from(n).map_err(|_| out_of_range())?)?;
+                }
+                array.data.len() * 4
+            }
+            (64, FitsData::IntegersI64(array)) => {
+                for n in &array.data {
+                    let n = n.or(blank).ok_or_else(missing_blank)?;
+                    writer.write_i64::<BigEndian>(n)?;
+                }
+                array.data.len() * 8
+            }
+            (64, FitsData::IntegersU64(array)) => {
+                for n in &array.data {
+                    let n = n.map(|n| (n ^ 0x8000_0000_0000_0000) as i64);
+                    let n = n.or(blank).ok_or_else(missing_blank)?;
+                    writer.write_i64::<BigEndian>(n)?;
+                }
+                array.data.len() * 8
+            }
+            (-32, FitsData::FloatingPoint32(array)) => {
+                for n in &array.data {
+                    writer.write_f32::<BigEndian>(*n)?;
+                }
+                array.data.len() * 4
+            }
+            (-64, FitsData::FloatingPoint64(array)) => {
+                for n in &array.data {
+                    writer.write_f64::<BigEndian>(*n)?;
+                }
+                array.data.len() * 8
+            }
+            _ => return Err(invalid_data("Data array does not match BITPIX")),
+        } as u64;
+        let padding = padded_length(len) - len;
+        writer.write_all(&vec![0u8; padding as usize])?;
+        writer.flush()
+    }
+}
+
+/// Choose the `BLANK` value of integers stored between `min` and `max`,
+/// among the values not taken by `stored`, preferring `preferred`.
+///
+/// Return [`None`] if `stored` has no undefined value.
+fn free_blank<I, T>(stored: I, preferred: T, min: T, max: T) -> Result<Option<i64>, FitsError>
+where
+    I: Iterator<Item = Option<i64>>,
+    T: Into<i64>,
+{
+    let mut has_blank = false;
+    let mut defined = Vec::new();
+    for n in stored {
+        match n {
+            Some(n) => defined.push(n),
+            None => has_blank = true,
+        }
+    }
+    if !has_blank {
+        return Ok(None);
+    }
+    let preferred = preferred.into();
+    if !defined.contains(&preferred) {
+        return Ok(Some(preferred));
+    }
+    defined.sort_unstable();
+    defined.dedup();
+    let (min, max) = (min.into(), max.into());
+    let mut candidate = min;
+    for n in defined {
+        if n > candidate {
+            break;
+        } else if candidate == max {
+            return Err(FitsError::NoFreeBlank);
+        }
+        candidate += 1;
+    }
+    Ok(Some(candidate))
+}
+
+/// Append the strings of `CONTINUE` cards to the string value of the
+/// preceding keyword when it ends with `&`, following the long string
+/// convention.
+fn merge_long_strings(
+    cards: Vec<((HeaderKeyWord, Option<HeaderValueComment>), CardImage)>,
+) -> Vec<OriginalLine> {
+    let mut merged: Vec<OriginalLine> = Vec::new();
+    for ((key, value_comment), card) in cards {
+        if key == "CONTINUE" {
+            if let Some(previous) = merged.last_mut() {
+                if let (Some(previous_value), Some(next)) =
+                    (previous.line.1.as_mut(), value_comment.as_ref())
+                {
+                    if previous_value.continue_string(next) {
+                        previous.cards.push(card);
+                        continue;
+                    }
+                }
+            }
+        }
+        merged.push(OriginalLine {
+            line: (key, value_comment),
+            cards: vec![card],
+        });
+    }
+    merged
+}
+
+/// Split `comment` in a head at most `len` bytes long and a tail, at a space
+/// if possible. The space is dropped, as comments of `CONTINUE` cards are
+/// joined with a space when read.
+fn split_comment(comment: &str, len: usize) -> (&str, &str) {
+    if comment.len() <= len {
+        return (comment, "");
+    }
+    let mut end = len;
+    while !comment.is_char_boundary(end) {
+        end -= 1;
+    }
+    if comment[end..].starts_with(' ') {
+        (&comment[..end], &comment[end + 1..])
+    } else if let Some(space) = comment[..end].rfind(' ') {
+        (&comment[..space], &comment[space + 1..])
+    } else {
+        comment.split_at(end)
+    }
+}
+
+/// Key as stored in the header. Keys starting with `HIERARCH ` are
+/// `HIERARCH` keywords, whose words are separated by a single space. Other
+/// keys must be standard keywords. Return [`None`] for an invalid keyword.
+fn normalize_key(key: &str) -> Option<String> {
+    if let Some(name) = key.strip_prefix(HIERARCH_PREFIX) {
+        let words: Vec<_> = key_words(name).collect();
+        if words.is_empty()
+            || !words
+                .iter()
+                .all(|word| word.bytes().all(|c| c.is_ascii_graphic() && c != EQUAL_U8))
+        {
+            return None;
+        }
+        Some(format!("{}{}", HIERARCH_PREFIX, words.join(" ")))
+    } else if !key.is_empty()
+        && key.len() <= 8
+        && key
+            .bytes()
+            .all(|c| c.is_ascii_uppercase() || c.is_ascii_digit() || c == b'-' || c == b'_')
+    {
+        Some(String::from(key))
+    } else {
+        None
+    }
+}
+
+/// Words of a `HIERARCH` keyword, separated by spaces or dots.
+fn key_words(key: &str) -> impl Iterator<Item = &str> + Clone {
+    key.split(&[' ', '.'][..]).filter(|word| !word.is_empty())
+}
+
+/// Whether `stored` is a `HIERARCH` keyword made of `words`.
+fn hierarch_matches<'a, I: Iterator<Item = &'a str>>(stored: &str, words: I) -> bool {
+    stored.starts_with(HIERARCH_PREFIX) && stored[HIERARCH_PREFIX.len()..].split(' ').eq(words)
+}
+
+fn invalid_data(msg: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, msg)
+}
+
+/// Offsets in the flattened array of shape `naxis` of the first element of
+/// each row of the section from `start` to `end` by `step`, whose shape is
+/// `shape`.
+fn section_rows(
+    naxis: &[usize],
+    shape: &[usize],
+    start: &[usize],
+    end: &[usize],
+    step: &[usize],
+) -> Vec<usize> {
+    let mut rows = Vec::new();
+    if shape.is_empty() || shape.contains(&0) {
+        return rows;
+    }
+    // Number of elements between two consecutive indices on each axis
+    let mut strides = vec![1; naxis.len()];
+    for i in 1..naxis.len() {
+        strides[i] = strides[i - 1] * naxis[i - 1];
+    }
+    let mut index = start.to_vec();
+    'rows: loop {
+        rows.push(index.iter().zip(&strides).map(|(i, s)| i * s).sum());
+        // Move to the next row, as an odometer over axes 2 to n
+        let mut axis = 1;
+        loop {
+            if axis == naxis.len() {
+                break 'rows;
+            }
+            index[axis] += step[axis];
+            if index[axis] < end[axis] {
+                break;
+            }
+            index[axis] = start[axis];
+            axis += 1;
+        }
+    }
+    rows
+}
+
+/// Whether `key` is a commentary keyword, whose cards hold free text and no
+/// value.
+fn is_commentary_keyword(key: &str) -> bool {
+    matches!(key, "COMMENT" | "HISTORY" | "")
+}
+
+/// Whether `key` is one of the mandatory keywords written first in a header.
+fn is_mandatory_keyword(key: &str) -> bool {
+    match key {
+        "SIMPLE" | "XTENSION" | "BITPIX" | "NAXIS" | "PCOUNT" | "GCOUNT" | "END" => true,
+        _ => key.starts_with("NAXIS") && key[5..].parse::<u32>().is_ok(),
+    }
+}
+
+const EQUAL_U8: u8 = '=' as u8;
+const SPACE_U8: u8 = ' ' as u8;
+const SLASH_U8: u8 = '/' as u8;
+const QUOTE_U8: u8 = '\'' as u8;
+const T_U8: u8 = 'T' as u8;
+const F_U8: u8 = 'F' as u8;
+
+impl HeaderValue {
+    fn new(value: &[u8]) -> Option<HeaderValue> {
+        HeaderValue::new_character_string(value)
+            .or_else(|| HeaderValue::new_logical(value))
+            .or_else(|| HeaderValue::new_integer(value))
+            .or_else(|| HeaderValue::new_real_floating(value))
+            .or_else(|| HeaderValue::new_complex(value))
+    }
+
+    fn new_character_string(subcard: &[u8]) -> Option<HeaderValue> {
+        let start = subcard.iter().position(|c| *c != SPACE_U8)?;
+        if subcard[start] != QUOTE_U8 {
+            return None;
+        }
+        let subcard = &subcard[start + 1..];
+        let mut s = String::new();
+        let mut prev_single_quote = false;
+        let mut white_space_count = 0;
+        for (i, c) in subcard.iter().enumerate() {
+            if prev_single_quote {
+                if *c == QUOTE_U8 {
+                    while white_space_count > 0 {
+                        s.push(' ');
+                        white_space_count -= 1;
+                    }
+                    s.push(*c as char);
+                    prev_single_quote = false;
+                } else {
+                    break;
+                }
+            } else if *c == QUOTE_U8 {
+                prev_single_quote = true;
+                continue;
+            } else if i > 0 && *c == SPACE_U8 {
+                white_space_count += 1;
+            } else {
+                while white_space_count > 0 {
+                    s.push(' ');
+                    white_space_count -= 1;
+                }
+                s.push(*c as char);
+            }
+        }
+        Some(HeaderValue::CharacterString(s))
+    }
+
+    fn new_logical(value: &[u8]) -> Option<HeaderValue> {
+        let mut b = None;
+        for c in value {
+            if *c == SPACE_U8 {
+                continue;
+            }
+            b = match (b, *c) {
+                (None, T_U8) => Some(true),
+                (None, F_U8) => Some(false),
+                _ => return None,
+            };
+        }
+        b.map(HeaderValue::Logical)
+    }
+
+    fn new_integer(value: &[u8]) -> Option<HeaderValue> {
+        from_utf8(value)
+            .ok()
+            .and_then(|string| {
+                let trimmed = string.trim();
+                trimmed.parse::<i64>().ok()
+            })
+            .map(HeaderValue::IntegerNumber)
+    }
+
+    fn new_real_floating(value: &[u8]) -> Option<HeaderValue> {
+        from_utf8(value)
+            .ok()
+            .and_then(parse_real)
+            .map(HeaderValue::RealFloatingNumber)
+    }
+
+    /// Parse a complex value written as `(real, imaginary)`.
+    fn new_complex(value: &[u8]) -> Option<HeaderValue> {
+        let string = from_utf8(value).ok()?.trim();
+        if !string.starts_with('(') || !string.ends_with(')') {
+            return None;
+        }
+        let mut parts = string[1..string.len() - 1].split(',');
+        let (re, im) = match (parts.next(), parts.next(), parts.next()) {
+            (Some(re), Some(im), None) => (re.trim(), im.trim()),
+            _ => return None,
+        };
+        if let (Ok(re), Ok(im)) = (re.parse::<i64>(), im.parse::<i64>()) {
+            Some(HeaderValue::ComplexIntegerNumber(re, im))
+        } else if let (Some(re), Some(im)) = (parse_real(re), parse_real(im)) {
+            Some(HeaderValue::ComplexFloatingNumber(re, im))
+        } else {
+            None
+        }
+    }
+}
+
+impl From<bool> for HeaderValue {
+    fn from(b: bool) -> HeaderValue {
+        HeaderValue::Logical(b)
+    }
+}
+
+impl From<i32> for HeaderValue {
+    fn from(n: i32) -> HeaderValue {
+        HeaderValue::IntegerNumber(n as i64)
+    }
+}
+
+impl From<i64> for HeaderValue {
+    fn from(n: i64) -> HeaderValue {
+        HeaderValue::IntegerNumber(n)
+    }
+}
+
+impl From<f64> for HeaderValue {
+    fn from(n: f64) -> HeaderValue {
+        HeaderValue::RealFloatingNumber(n)
+    }
+}
+
+impl From<&str> for HeaderValue {
+    fn from(s: &str) -> HeaderValue {
+        HeaderValue::CharacterString(String::from(s))
+    }
+}
+
+impl From<String> for HeaderValue {
+    fn from(s: String) -> HeaderValue {
+        HeaderValue::CharacterString(s)
+    }
+}
+
+/// Type that can be read from a [`HeaderValue`] with [`Hdu::get`].
+///
+/// Integer values are converted to floating point numbers when a real
+/// number is requested.
+pub trait FromHeaderValue: Sized {
+    /// Description of the expected value, used in error messages.
+    const EXPECTED: &'static str;
+
+    /// Convert `value`, or return [`None`] if it has another type or is out
+    /// of range.
+    fn from_header_value(value: &HeaderValue) -> Option<Self>;
+}
+
+impl FromHeaderValue for bool {
+    const EXPECTED: &'static str = "logical";
+
+    fn from_header_value(value: &HeaderValue) -> Option<Self> {
+        match *value {
+            HeaderValue::Logical(b) => Some(b),
+            _ => None,
+        }
+    }
+}
+
+impl FromHeaderValue for i64 {
+    const EXPECTED: &'static str = "integer";
+
+    fn from_header_value(value: &HeaderValue) -> Option<Self> {
+        match *value {
+            HeaderValue::IntegerNumber(n) => Some(n),
+            _ => None,
+        }
+    }
+}
+
+impl FromHeaderValue for i32 {
+    const EXPECTED: &'static str = "32-bit integer";
+
+    fn from_header_value(value: &HeaderValue) -> Option<Self> {
+        i64::from_header_value(value).and_then(|n| i32::try_from(n).ok())
+    }
+}
+
+impl FromHeaderValue for usize {
+    const EXPECTED: &'static str = "non-negative integer";
+
+    fn from_header_value(value: &HeaderValue) -> Option<Self> {
+        i64::from_header_value(value).and_then(|n| usize::try_from(n).ok())
+    }
+}
+
+impl FromHeaderValue for f64 {
+    const EXPECTED: &'static str = "real number";
+
+    fn from_header_value(value: &HeaderValue) -> Option<Self> {
+        match *value {
+            HeaderValue::IntegerNumber(n) => Some(n as f64),
+            HeaderValue::RealFloatingNumber(n) => Some(n),
+            _ => None,
+        }
+    }
+}
+
+impl FromHeaderValue for f32 {
+    const EXPECTED: &'static str = "real number";
+
+    fn from_header_value(value: &HeaderValue) -> Option<Self> {
+        f64::from_header_value(value).map(|n| n as f32)
+    }
+}
+
+impl FromHeaderValue for (f64, f64) {
+    const EXPECTED: &'static str = "complex number";
+
+    fn from_header_value(value: &HeaderValue) -> Option<Self> {
+        match *value {
+            HeaderValue::ComplexIntegerNumber(re, im) => Some((re as f64, im as f64)),
+            HeaderValue::ComplexFloatingNumber(re, im) => Some((re, im)),
+            _ => None,
+        }
+    }
+}
+
+impl FromHeaderValue for String {
+    const EXPECTED: &'static str = "character string";
+
+    fn from_header_value(value: &HeaderValue) -> Option<Self> {
+        match *value {
+            HeaderValue::CharacterString(ref s) => Some(s.clone()),
+            _ => None,
+        }
+    }
+}
+
+impl HeaderValue {
+    /// Format value as written in a card image, following the fixed format
+    /// defined in FITS standard.
+    fn to_card_value(&self) -> String {
+        match *self {
+            // A string of spaces is read as a single space, so the empty
+            // string is written without padding
+            HeaderValue::CharacterString(ref s) if s.is_empty() => String::from("''"),
+            HeaderValue::CharacterString(ref s) => {
+                format!("'{:<8}'", s.replace('\'', "''"))
+            }
+            HeaderValue::Logical(b) => format!("{:>20}", if b { "T" } else { "F" }),
+            HeaderValue::IntegerNumber(n) => format!("{:>20}", n),
+            HeaderValue::RealFloatingNumber(n) => format!("{:>20}", format_real(n)),
+            HeaderValue::ComplexIntegerNumber(re, im) => {
+                format!("{:>20}", format!("({}, {})", re, im))
+            }
+            HeaderValue::ComplexFloatingNumber(re, im) => format!(
+                "{:>20}",
+                format!("({}, {})", format_real(re), format_real(im))
+            ),
+        }
+    }
+}
+
+/// Parse a real number, accepting the Fortran `D` exponent of double
+/// precision numbers.
+fn parse_real(string: &str) -> Option<f64> {
+    f64::from_str(&string.trim().replace(['D', 'd'], "E")).ok()
+}
+
+/// Format a floating point number so that it is always read back as a real
+/// number, with an uppercase exponent as required by the standard.
+fn format_real(n: f64) -> String {
+    let s = format!("{:?}", n).to_uppercase();
+    if s.contains('.') || !s.chars().any(|c| c.is_ascii_digit()) {
+        s
+    } else if let Some(pos) = s.find('E') {
+        format!("{}.0{}", &s[..pos], &s[pos..])
+    } else {
+        format!("{}.0", s)
+    }
+}
+
+/// Split the value and the comment of a card image at the `/` starting the
+/// comment. Slashes inside a character string are part of the value.
+fn split_value_comment(value_comment: &[u8]) -> (&[u8], Option<&[u8]>) {
+    let mut in_string = false;
+    for (i, c) in value_comment.iter().enumerate() {
+        match *c {
+            // A doubled quote inside a string toggles twice, staying inside
+            QUOTE_U8 => in_string = !in_string,
+            SLASH_U8 if !in_string => {
+                return (&value_comment[..i], Some(&value_comment[i + 1..]));
+            }
+            _ => (),
+        }
+    }
+    (value_comment, None)
+}
+
+impl HeaderValueComment {
+    fn new(value_comment: &[u8]) -> HeaderValueComment {
+        let (value_slice, comment_slice) = split_value_comment(value_comment);
+        HeaderValueComment {
+            value: HeaderValue::new(value_slice),
+            comment: comment_slice.map(|slice| {
+                let mut comment = HeaderComment::new();
+                for c in slice {
+                    comment.push(*c as char);
+                }
+                String::from(comment.trim())
+            }),
+        }
+    }
+
+    /// Append the string of `next`, from a `CONTINUE` card, if this string
+    /// ends with `&`. Return whether `next` was appended.
+    fn continue_string(&mut self, next: &HeaderValueComment) -> bool {
+        match (&mut self.value, &next.value) {
+            (Some(HeaderValue::CharacterString(s)), Some(HeaderValue::CharacterString(n)))
+                if s.ends_with('&') =>
+            {
+                s.pop();
+                s.push_str(n);
+            }
+            _ => return false,
+        }
+        if let Some(ref next_comment) = next.comment {
+            match self.comment {
+                Some(ref mut comment) if !comment.is_empty() => {
+                    comment.push(' ');
+                    comment.push_str(next_comment);
+                }
+                _ => self.comment = Some(next_comment.clone()),
+            }
+        }
+        true
+    }
+}
+
+impl CardImage {
+    fn new() -> CardImage {
+        CardImage([0u8; 80])
+    }
+
+    /// Format the card images of a header line. A long string is continued
+    /// over `CONTINUE` cards.
+    fn from_line(
+        (key, value_comment): &(HeaderKeyWord, Option<HeaderValueComment>),
+    ) -> Result<Vec<CardImage>, Error> {
+        match value_comment {
+            Some(HeaderValueComment {
+                value: Some(HeaderValue::CharacterString(value)),
+                comment,
+            }) if !CardImage::fits_string(key, value) => {
+                CardImage::from_long_string(key, value, comment.as_ref())
+            }
+            Some(value_comment) => Ok(vec![CardImage::from_key_value(
+                key,
+                value_comment.value.as_ref(),
+                value_comment.comment.as_ref(),
+            )?]),
+            None => Ok(vec![CardImage::from_key_value(key, None, None)?]),
+        }
+    }
+
+    /// Format a card image. Fail if the key or the value does not fit.
+    ///
+    /// A comment without value is written after `= ` as the comment of an
+    /// undefined value, unless `key` is a commentary keyword. A comment too
+    /// long for the card is truncated.
+    fn from_key_value(
+        key: &str,
+        value: Option<&HeaderValue>,
+        comment: Option<&HeaderComment>,
+    ) -> Result<CardImage, Error> {
+        let hierarch = key.starts_with(HIERARCH_PREFIX);
+        if key.len() > 8 && !hierarch {
+            return Err(invalid_data("Keyword is longer than 8 characters"));
+        }
+        let mut card = format!("{:<8}", key);
+        let undefined = value.is_none() && comment.is_some() && !is_commentary_keyword(key);
+        if undefined {
+            card.push_str(if hierarch { " =" } else { "=" });
+        }
+        if let Some(value) = value {
+            if hierarch {
+                card.push_str(" = ");
+                card.push_str(value.to_card_value().trim_start());
+            } else {
+                card.push_str("= ");
+                card.push_str(&value.to_card_value());
+            }
+            if card.len() > 80 {
+                return Err(invalid_data("Value does not fit in card image"));
+            }
+        }
+        if let Some(comment) = comment {
+            if value.is_some() || undefined {
+                card.push_str(" / ");
+            }
+            card.push_str(comment);
+        }
+        Ok(CardImage::from_string(&card))
+    }
+
+    /// Whether the string `value` of `key` fits in a single card image.
+    fn fits_string(key: &str, value: &str) -> bool {
+        let quoted = value.replace('\'', "''").len() + 2;
+        if key.starts_with(HIERARCH_PREFIX) {
+            key.len() + 3 + quoted <= 80
+        } else {
+            10 + quoted <= 80
+        }
+    }
+
+    /// Format a string value too long for a single card image as a card
+    /// followed by `CONTINUE` cards, following the long string convention.
+    ///
+    /// Each card but the last ends its string with `&`. The comment is
+    /// written on the last card, and spills over more `CONTINUE` cards if it
+    /// does not fit, preferably between words. A string ending with spaces
+    /// is followed by an empty string, so that its trailing spaces are kept.
+    fn from_long_string(
+        key: &str,
+        value: &str,
+        comment: Option<&HeaderComment>,
+    ) -> Result<Vec<CardImage>, Error> {
+        const CONTINUE_START: &str = "CONTINUE  '";
+        let first_start = if key.starts_with(HIERARCH_PREFIX) {
+            format!("{} = '", key)
+        } else if key.len() > 8 {
+            return Err(invalid_data("Keyword is longer than 8 characters"));
+        } else {
+            format!("{:<8}= '", key)
+        };
+        // Room between the quotes of a card, keeping one column for `&`
+        let mut room = 80usize.saturating_sub(first_start.len() + 2);
+        if room < 2 {
+            return Err(invalid_data("Keyword is too long for a string value"));
+        }
+        let mut chunks = vec![String::new()];
+        for c in value.chars() {
+            let escaped = if c == '\'' {
+                "''".to_owned()
+            } else {
+                c.to_string()
+            };
+            if chunks.last().unwrap().len() + escaped.len() > room {
+                chunks.push(String::new());
+                room = 80 - CONTINUE_START.len() - 2;
+            }
+            chunks.last_mut().unwrap().push_str(&escaped);
+        }
+        if value.ends_with(' ') {
+            chunks.push(String::new());
+        }
+        let mut cards = Vec::new();
+        let last = chunks.pop().unwrap();
+        for (i, chunk) in chunks.iter().enumerate() {
+            let start = if i == 0 { &first_start } else { CONTINUE_START };
+            cards.push(CardImage::from_string(&format!("{}{}&'", start, chunk)));
+        }
+        let start = if cards.is_empty() {
+            &first_start
+        } else {
+            CONTINUE_START
+        };
+        let mut card = format!("{}{}", start, last);
+        let mut comment = comment.map(String::as_str).unwrap_or("");
+        // Room for the comment after `' / ` on the last card
+        while !comment.is_empty() && card.len() + 4 + comment.len() > 80 {
+            card.push_str("&'");
+            let (head, tail) = split_comment(comment, 80usize.saturating_sub(card.len() + 3));
+            if !head.is_empty() {
+                card.push_str(" / ");
+                card.push_str(head);
+            }
+            cards.push(CardImage::from_string(&card));
+            card = String::from(CONTINUE_START);
+            comment = tail;
+        }
+        card.push('\'');
+        if !comment.is_empty() {
+            card.push_str(" / ");
+            card.push_str(comment);
+        }
+        cards.push(CardImage::from_string(&card));
+        Ok(cards)
+    }
+
+    /// Card image holding `card`, padded with spaces or truncated to 80
+    /// columns.
+    fn from_string(card: &str) -> CardImage {
+        let mut image = CardImage([SPACE_U8; 80]);
+        for (c, byte) in card.bytes().take(80).zip(image.0.iter_mut()) {
+            *byte = c;
+        }
+        image
+    }
+
+    fn to_header_key_value(&self) -> (HeaderKeyWord, Option<HeaderValueComment>) {
+        let card = self.0;
+        let keyword = &card[0..8];
+        let value_indicator = &card[8..10];
+        let value_comment = &card[10..80];
+        let mut key = HeaderKeyWord::new();
+        for c in keyword {
+            if *c == SPACE_U8 {
+                break;
+            }
+            key.push(*c as char);
+        }
+        if key == "HIERARCH" {
+            if let Some(equal) = card[8..].iter().position(|c| *c == EQUAL_U8) {
+                let equal = 8 + equal;
+                let words: Vec<_> = card[8..equal]
+                    .split(|c| *c == SPACE_U8)
+                    .filter(|word| !word.is_empty())
+                    .map(|word| word.iter().map(|c| *c as char).collect::<String>())
+                    .collect();
+                if !words.is_empty() {
+                    let key = format!("{}{}", HIERARCH_PREFIX, words.join(" "));
+                    let val = HeaderValueComment::new(&card[equal + 1..80]);
+                    return (key, Some(val));
+                }
+            }
+        }
+        if (!is_commentary_keyword(&key)
+            && value_indicator[0] == EQUAL_U8
+            && value_indicator[1] == SPACE_U8)
+            || (key == "CONTINUE" && value_indicator == [SPACE_U8, SPACE_U8])
+        {
+            let val = HeaderValueComment::new(value_comment);
+            (key, Some(val))
+        } else {
+            // Commentary card, such as COMMENT or HISTORY: columns 9 to 80
+            // hold free text.
+            let text: String = card[8..80].iter().map(|c| *c as char).collect();
+            let text = text.trim_end();
+            if text.is_empty() {
+                (key, None)
+            } else {
+                let val = HeaderValueComment {
+                    value: None,
+                    comment: Some(String::from(text)),
+                };
+                (key, Some(val))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::{CardImage, Fits, FitsData, FitsDataArray, Hdu, HeaderValue};
+    use compress::Decompress;
+    use error::FitsError;
+    use std::env;
+    use std::fs;
+    use std::io::{Cursor, ErrorKind};
+    use std::path::PathBuf;
+    use std::process;
+
+    impl CardImage {
+        fn from(s: &str) -> CardImage {
+            let mut card = [' ' as u8; 80];
+            for (i, c) in s.chars().enumerate() {
+                card[i] = c as u8;
+            }
+            CardImage(card)
+        }
+    }
+
+    /// Path of a temporary file named after `name`, unique to the process.
+    pub(crate) fn temp_path(name: &str) -> PathBuf {
+        env::temp_dir().join(format!("fitrs-{}-{}", process::id(), name))
+    }
+
+    /// Write a file with a header made of `cards`, followed by `blocks`
+    /// blocks of zeros.
+    fn write_header_only(path: &PathBuf, cards: &[&str], blocks: usize) {
+        let mut header = String::new();
+        for card in cards.iter() {
+            header.push_str(&format!("{:<80}", card));
+        }
+        let mut bytes = format!("{:<2880}", header).into_bytes();
+        bytes.extend(vec![0; 2880 * blocks]);
+        fs::write(path, bytes).unwrap();
+    }
+
+    #[test]
+    fn read_card_image_character_string() {
+        let card = CardImage::from("AUTHOR  = 'Malik Olivier Boussejra <malik@boussejra.com>' /");
+        let header_key_value = card.to_header_key_value();
+        assert_eq!(header_key_value.0, String::from("AUTHOR"));
+        let value_comment = header_key_value.1.unwrap();
+        assert_eq!(
+            value_comment.value,
+            Some(HeaderValue::CharacterString(String::from(
+                "Malik Olivier Boussejra <malik@boussejra.com>",
+            )))
+        );
+        assert_eq!(value_comment.comment, Some(String::from("")));
+    }
+
+    #[test]
+    fn read_card_image_no_comment() {
+        let card = CardImage::from("AUTHOR  = ''");
+        let header_key_value = card.to_header_key_value();
+        let value_comment = header_key_value.1.unwrap();
+        assert_eq!(
+            value_comment.value,
+            Some(HeaderValue::CharacterString(String::from("")))
+        );
+        assert_eq!(value_comment.comment, None);
+    }
+
+    #[test]
+    fn read_card_image_character_trailing_space() {
+        let card = CardImage::from("AUTHOR  = '  ab d  '");
+        let header_key_value = card.to_header_key_value();
+        let value_comment = header_key_value.1.unwrap();
+        assert_eq!(
+            value_comment.value,
+            Some(HeaderValue::CharacterString(String::from("  ab d")))
+        );
+    }
+
+    #[test]
+    fn read_card_image_character_blank() {
+        let card = CardImage::from("AUTHOR  = '  '");
+        let header_key_value = card.to_header_key_value();
+        let value_comment = header_key_value.1.unwrap();
+        assert_eq!(
+            value_comment.value,
+            Some(HeaderValue::CharacterString(String::from(" ")))
+        );
+    }
+
+    #[test]
+    fn read_card_image_character_logical_true() {
+        let card = CardImage::from("SIMPLE  =                    T /                     ");
+        let header_key_value = card.to_header_key_value();
+        let value_comment = header_key_value.1.unwrap();
+        assert_eq!(value_comment.value, Some(HeaderValue::Logical(true)));
+    }
+
+    #[test]
+    fn read_card_image_character_logical_false() {
+        let card = CardImage::from("SIMPLE  =                    F /                     ");
+        let header_key_value = card.to_header_key_value();
+        let value_comment = header_key_value.1.unwrap();
+        assert_eq!(value_comment.value, Some(HeaderValue::Logical(false)));
+    }
+
+    #[test]
+    fn read_card_image_character_integer() {
+        let card = CardImage::from("BITPIX  =                    8 /                     ");
+        let header_key_value = card.to_header_key_value();
+        let value_comment = header_key_value.1.unwrap();
+        assert_eq!(value_comment.value, Some(HeaderValue::IntegerNumber(8)));
+    }
+
+    #[test]
+    fn read_card_image_character_real() {
+        let card =
+            CardImage::from("EXPTIME =              13501.5 / Total exposure time (seconds)");
+        let header_key_value = card.to_header_key_value();
+        let value_comment = header_key_value.1.unwrap();
+        assert_eq!(
+            value_comment.value,
+            Some(HeaderValue::RealFloatingNumber(13501.5))
+        );
+    }
+
+    #[test]
+    fn read_card_image_character_real_exp() {
+        let card = CardImage::from("CDELT1  =      -1.666667E-03 /");
+        let header_key_value = card.to_header_key_value();
+        let value_comment = header_key_value.1.unwrap();
+        assert_eq!(
+            value_comment.value,
+            Some(HeaderValue::RealFloatingNumber(-1.666667E-03))
+        );
+    }
+
+    #[test]
+    fn read_card_image_character_string_with_slash() {
+        let card =
+            CardImage::from("DATE-OBS= '2019/03/04'         / Date of observation (dd/mm/yy)");
+        let value_comment = card.to_header_key_value().1.unwrap();
+        assert_eq!(value_comment.value, Some(HeaderValue::from("2019/03/04")));
+        assert_eq!(
+            value_comment.comment,
+            Some(String::from("Date of observation (dd/mm/yy)"))
+        );
+
+        let card = CardImage::from("FILENAME= '/data/raw/x.fits'");
+        let value_comment = card.to_header_key_value().1.unwrap();
+        assert_eq!(
+            value_comment.value,
+            Some(HeaderValue::from("/data/raw/x.fits"))
+        );
+        assert_eq!(value_comment.comment, None);
+    }
+
+    #[test]
+    fn read_card_image_character_string_with_quote_and_slash() {
+        let card = CardImage::from("TITLE   = 'It''s a/b ''test''' / a 'quoted' / comment");
+        let value_comment = card.to_header_key_value().1.unwrap();
+        assert_eq!(
+            value_comment.value,
+            Some(HeaderValue::from("It's a/b 'test'"))
+        );
+        assert_eq!(
+            value_comment.comment,
+            Some(String::from("a 'quoted' / comment"))
+        );
+    }
+
+    #[test]
+    fn read_card_image_value_without_space_before_comment() {
+        let card = CardImage::from("NAXIS   =                    2/number of axes");
+        let value_comment = card.to_header_key_value().1.unwrap();
+        assert_eq!(value_comment.value, Some(HeaderValue::IntegerNumber(2)));
+        assert_eq!(value_comment.comment, Some(String::from("number of axes")));
+
+        let card = CardImage::from("UNDEF   =                      / undefined value");
+        let value_comment = card.to_header_key_value().1.unwrap();
+        assert_eq!(value_comment.value, None);
+        assert_eq!(value_comment.comment, Some(String::from("undefined value")));
+    }
+
+    #[test]
+    fn read_card_image_real_fortran_exponent() {
+        let card = CardImage::from("EXPTIME =            1.0D+03 /");
+        let value_comment = card.to_header_key_value().1.unwrap();
+        assert_eq!(
+            value_comment.value,
+            Some(HeaderValue::RealFloatingNumber(1000.0))
+        );
+
+        let card = CardImage::from("CDELT1  =         -1.5d-2");
+        let value_comment = card.to_header_key_value().1.unwrap();
+        assert_eq!(
+            value_comment.value,
+            Some(HeaderValue::RealFloatingNumber(-0.015))
+        );
+    }
+
+    #[test]
+    fn read_card_image_complex() {
+        let card = CardImage::from("CPLX    = (1.5, -2.0) / complex");
+        let value_comment = card.to_header_key_value().1.unwrap();
+        assert_eq!(
+            value_comment.value,
+            Some(HeaderValue::ComplexFloatingNumber(1.5, -2.0))
+        );
+        assert_eq!(value_comment.comment, Some(String::from("complex")));
+
+        let card = CardImage::from("CPLX    =             (12,-3)");
+        let value_comment = card.to_header_key_value().1.unwrap();
+        assert_eq!(
+            value_comment.value,
+            Some(HeaderValue::ComplexIntegerNumber(12, -3))
+        );
+
+        let card = CardImage::from("CPLX    =    (1.0D+01, 2)");
+        let value_comment = card.to_header_key_value().1.unwrap();
+        assert_eq!(
+            value_comment.value,
+            Some(HeaderValue::ComplexFloatingNumber(10.0, 2.0))
+        );
+    }
+
+    #[test]
+    fn read_card_image_invalid_complex() {
+        for card in &["CPLX    = (1.5)", "CPLX    = (1, 2, 3)", "CPLX    = (1, a)"] {
+            let value_comment = CardImage::from(card).to_header_key_value().1;
+            assert_eq!(value_comment.unwrap().value, None);
+        }
+    }
+
+    #[test]
+    fn write_complex_card_image() {
+        for value in &[
+            HeaderValue::ComplexIntegerNumber(12, -3),
+            HeaderValue::ComplexFloatingNumber(1.5, -2e100),
+        ] {
+            let card = CardImage::from_key_value("CPLX", Some(value), None).unwrap();
+            let value_comment = card.to_header_key_value().1.unwrap();
+            assert_eq!(value_comment.value.as_ref(), Some(value));
+        }
+    }
+
+    #[test]
+    fn read_card_image_free_format_logical() {
+        let card = CardImage::from("SIMPLE  = T");
+        let header_key_value = card.to_header_key_value();
+        let value_comment = header_key_value.1.unwrap();
+        assert_eq!(value_comment.value, Some(HeaderValue::Logical(true)));
+    }
+
+    #[test]
+    fn read_card_image_hierarch() {
+        let card = CardImage::from("HIERARCH ESO DET  CHIP TEMP = 150.3 / Chip temperature [K]");
+        let header_key_value = card.to_header_key_value();
+        assert_eq!(header_key_value.0, "HIERARCH ESO DET CHIP TEMP");
+        let value_comment = header_key_value.1.unwrap();
+        assert_eq!(
+            value_comment.value,
+            Some(HeaderValue::RealFloatingNumber(150.3))
+        );
+        assert_eq!(
+            value_comment.comment,
+            Some(String::from("Chip temperature [K]"))
+        );
+    }
+
+    #[test]
+    fn read_card_image_hierarch_without_value() {
+        let card = CardImage::from("HIERARCH is not followed by an equal sign");
+        let header_key_value = card.to_header_key_value();
+        assert_eq!(header_key_value.0, "HIERARCH");
+        let value_comment = header_key_value.1.unwrap();
+        assert_eq!(value_comment.value, None);
+        assert_eq!(
+            value_comment.comment,
+            Some(String::from(" is not followed by an equal sign"))
+        );
+    }
+
+    #[test]
+    fn hierarch_value_and_tree() {
+        let mut hdu = Hdu::empty();
+        hdu.insert("HIERARCH ESO DET CHIP TEMP", 150.3);
+        hdu.insert("HIERARCH ESO DET DIT", 10);
+        hdu.insert("HIERARCH ESO.OBS.NAME", "Field 1");
+        hdu.insert("HIERARCH ESO INS SHUT ST", true);
+        hdu.insert("OBJECT", "M31");
+
+        let temp = Some(&HeaderValue::RealFloatingNumber(150.3));
+        assert_eq!(hdu.value("HIERARCH ESO DET CHIP TEMP"), temp);
+        assert_eq!(hdu.value("ESO DET CHIP TEMP"), temp);
+        assert_eq!(hdu.value("ESO.DET.CHIP.TEMP"), temp);
+        assert_eq!(hdu.value("ESO DET CHIP"), None);
+
+        let tree = hdu.hierarch();
+        assert_eq!(tree.len(), 1);
+        let eso = &tree[0];
+        assert_eq!(eso.name, "ESO");
+        assert_eq!(eso.value, None);
+        let names: Vec<_> = eso.children.iter().map(|node| node.name).collect();
+        assert_eq!(names, ["DET", "OBS", "INS"]);
+        let det = eso.child("DET").unwrap();
+        assert_eq!(
+            det.child("CHIP").unwrap().child("TEMP").unwrap().value,
+            temp
+        );
+        assert_eq!(
+            det.child("DIT").unwrap().value,
+            Some(&HeaderValue::IntegerNumber(10))
+        );
+        assert!(det.child("DIT").unwrap().children.is_empty());
+    }
+
+    #[test]
+    fn insert_invalid_keyword() {
+        let mut hdu = Hdu::empty();
+        for key in &[
+            "ESO DET CHIP TEMP",
+            "ESO.OBS.NAME",
+            "LONGKEYWORD",
+            "object",
+            "HIERARCH ",
+        ] {
+            let error = hdu.try_insert(key, 1).unwrap_err();
+            assert_eq!(error.to_string(), format!("Invalid keyword name {:?}", key));
+        }
+        assert!(hdu.insert_after("SIMPLE", "DATE OBS", 1).is_err());
+        assert!(hdu.rename("SIMPLE", "ESO DET").is_err());
+        assert_eq!(hdu.value("ESO DET CHIP TEMP"), None);
+        hdu.try_insert("DATE-OBS", "2020-01-01").unwrap();
+        hdu.try_insert("HIERARCH ESO.OBS.NAME", "Field 1").unwrap();
+        assert_eq!(
+            hdu.value("ESO OBS NAME"),
+            Some(&HeaderValue::from("Field 1"))
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid keyword name \"LONGKEYWORD\"")]
+    fn insert_invalid_keyword_panics() {
+        Hdu::empty().insert("LONGKEYWORD", 1);
+    }
+
+    #[test]
+    fn write_hierarch() {
+        let path = temp_path("write_hierarch.fits");
+        let mut hdu = Hdu::new(&[1], vec![1.0f32]);
+        hdu.insert("HIERARCH ESO DET CHIP TEMP", 150.3);
+        hdu.insert("HIERARCH ESO INS SHUT ST", false);
+        hdu.insert("HIERARCH ESO OBS NAME", "Field 1");
+        Fits::create(&path, hdu).unwrap();
+
+        let header = fs::read(&path).unwrap();
+        let cards: Vec<_> = header.chunks(80).map(|card| card.to_vec()).collect();
+        assert!(
+            cards.contains(&format!("{:<80}", "HIERARCH ESO DET CHIP TEMP = 150.3").into_bytes())
+        );
+
+        let fits = Fits::open(&path).unwrap();
+        let hdu = &fits[0];
+        assert_eq!(
+            hdu.value("ESO DET CHIP TEMP"),
+            Some(&HeaderValue::RealFloatingNumber(150.3))
+        );
+        assert_eq!(
+            hdu.value("ESO INS SHUT ST"),
+            Some(&HeaderValue::Logical(false))
+        );
+        assert_eq!(
+            hdu.value("ESO OBS NAME"),
+            Some(&HeaderValue::from("Field 1"))
+        );
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn iterate_over_cards() {
+        let fits = Fits::open("test/testprog.fit").unwrap();
+        let hdu = &fits[0];
+        let mut cards = hdu.cards();
+        assert_eq!(
+            cards.next(),
+            Some((
+                "SIMPLE",
+                Some(&HeaderValue::Logical(true)),
+                Some("file does conform to FITS standard")
+            ))
+        );
+        assert_eq!(
+            cards.next().map(|(key, value, _)| (key, value)),
+            Some(("BITPIX", Some(&HeaderValue::IntegerNumber(32))))
+        );
+        assert!(hdu
+            .cards()
+            .all(|(key, _, _)| key != "END" && key != "CONTINUE"));
+        assert_eq!(
+            hdu.cards().filter(|&(key, _, _)| key == "COMMENT").count(),
+            8
+        );
+    }
+
+    #[test]
+    fn read_comments_and_history() {
+        let fits = Fits::open("test/testprog.fit").unwrap();
+        let hdu = &fits[0];
+        let comments = hdu.comments();
+        assert_eq!(comments.len(), 8);
+        assert_eq!(
+            comments[0],
+            "  FITS (Flexible Image Transport System) format is defined in 'Astronomy"
+        );
+        assert_eq!(comments[7], "this is the 5th template card");
+        assert_eq!(hdu.history(), ["this is the 6th template card"]);
+        assert!(fits[1].history().is_empty());
+        assert_eq!(
+            hdu.comment("SIMPLE"),
+            Some("file does conform to FITS standard")
+        );
+        assert_eq!(hdu.comment("NOTFOUND"), None);
+    }
+
+    #[test]
+    fn write_comments_and_history() {
+        let path = temp_path("write_comments_and_history.fits");
+        let mut hdu = Hdu::empty();
+        hdu.header.push((
+            String::from("HISTORY"),
+            Some(super::HeaderValueComment {
+                value: None,
+                comment: Some(String::from("  Created by fitrs")),
+            }),
+        ));
+        Fits::create(&path, hdu).unwrap();
+
+        let fits = Fits::open(&path).unwrap();
+        assert_eq!(fits[0].history(), ["  Created by fitrs"]);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn get_typed_values() {
+        let fits = Fits::open("test/testprog.fit").unwrap();
+        let hdu = &fits[0];
+        assert!(hdu.get::<bool>("SIMPLE").unwrap());
+        assert_eq!(hdu.get::<i64>("KY_IKYJ").unwrap(), 51);
+        assert_eq!(hdu.get::<i32>("BITPIX").unwrap(), 32);
+        assert_eq!(hdu.get::<usize>("NAXIS1").unwrap(), 10);
+        assert_eq!(hdu.get::<f64>("KY_IKYE").unwrap(), -1.3346E+01);
+        assert_eq!(hdu.get::<f32>("KY_IKYE").unwrap(), -1.3346E+01);
+        // Integers are coerced to real numbers
+        assert_eq!(hdu.get::<f64>("KY_IKYJ").unwrap(), 51.0);
+        let xtension: String = fits[1].get("XTENSION").unwrap();
+        assert_eq!(xtension, "BINTABLE");
+    }
+
+    #[test]
+    fn get_typed_value_errors() {
+        let fits = Fits::open("test/testprog.fit").unwrap();
+        let hdu = &fits[0];
+        match hdu.get::<f64>("NOTFOUND") {
+            Err(FitsError::MissingKeyword { hdu: Some(0), .. }) => (),
+            other => panic!("Should be MissingKeyword! Got {:?}", other),
+        }
+        let error = hdu.get::<i64>("KY_IKYE").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Expected integer for keyword KY_IKYE in HDU 0, found RealFloatingNumber(-13.346)"
+        );
+        assert!(hdu.get::<bool>("KY_IKYJ").is_err());
+        assert!(hdu.get::<String>("SIMPLE").is_err());
+
+        let mut hdu = Hdu::empty();
+        hdu.insert("BIG", 3_000_000_000i64);
+        hdu.insert("NEG", -1);
+        hdu.insert("CPLX", HeaderValue::ComplexIntegerNumber(1, -2));
+        assert_eq!(hdu.get::<i64>("BIG").unwrap(), 3_000_000_000);
+        match hdu.get::<i32>("BIG") {
+            Err(FitsError::UnexpectedType {
+                hdu: None,
+                expected: "32-bit integer",
+                ..
+            }) => (),
+            other => panic!("Should be UnexpectedType! Got {:?}", other),
+        }
+        assert!(hdu.get::<usize>("NEG").is_err());
+        assert_eq!(hdu.get::<(f64, f64)>("CPLX").unwrap(), (1.0, -2.0));
+    }
+
+    #[test]
+    fn edit_header() {
+        let mut hdu = Hdu::new(&[2], vec![1.0f32, 2.0]);
+        hdu.insert("OBJECT", "M31");
+        hdu.insert("EXPTIME", 10.0);
+        hdu.insert_before("EXPTIME", "OBSERVER", "Edwin").unwrap();
+        hdu.insert_after("OBJECT", "RA", 10.68).unwrap();
+        hdu.set_comment("OBJECT", "Target").unwrap();
+        // Moving a keyword keeps its comment
+        hdu.insert_after("EXPTIME", "OBJECT", "M32").unwrap();
+        hdu.rename("RA", "RA_OBJ").unwrap();
+        hdu.insert("DEC", 41.27);
+        assert_eq!(hdu.remove("DEC").unwrap(), Some(HeaderValue::from(41.27)));
+        hdu.add_history("Edited").unwrap();
+
+        let keys: Vec<_> = hdu.cards().map(|(key, _, _)| key).collect();
+        assert_eq!(
+            keys,
+            [
+                "SIMPLE", "BITPIX", "NAXIS", "NAXIS1", "RA_OBJ", "OBSERVER", "EXPTIME", "OBJECT",
+                "HISTORY"
+            ]
+        );
+        assert_eq!(hdu.value("OBJECT"), Some(&HeaderValue::from("M32")));
+        assert_eq!(hdu.comment("OBJECT"), Some("Target"));
+        assert_eq!(hdu.value("RA_OBJ"), Some(&HeaderValue::from(10.68)));
+        assert_eq!(hdu.history(), ["Edited"]);
+    }
+
+    #[test]
+    fn edit_header_errors() {
+        let mut hdu = Hdu::new(&[2], vec![1.0f32, 2.0]);
+        hdu.insert("OBJECT", "M31");
+        match hdu.insert_before("NOTFOUND", "KEY", 1) {
+            Err(FitsError::MissingKeyword { keyword, .. }) => assert_eq!(keyword, "NOTFOUND"),
+            other => panic!("Should be MissingKeyword! Got {:?}", other),
+        }
+        assert!(hdu.value("KEY").is_none());
+        assert!(hdu.set_comment("NOTFOUND", "comment").is_err());
+        assert!(hdu.rename("NOTFOUND", "KEY").is_err());
+        assert!(hdu.rename("OBJECT", "NAXIS2").is_err());
+        assert!(hdu.rename("OBJECT", "BITPIX").is_err());
+        assert!(hdu.rename("NAXIS1", "WIDTH").is_err());
+        assert!(hdu.remove("NOTFOUND").is_err());
+        match hdu.remove("BITPIX") {
+            Err(FitsError::InvalidKeyword { keyword, .. }) => assert_eq!(keyword, "BITPIX"),
+            other => panic!("Should be InvalidKeyword! Got {:?}", other),
+        }
+        assert_eq!(hdu.value("BITPIX"), Some(&HeaderValue::IntegerNumber(-32)));
+    }
+
+    #[test]
+    fn insert_mandatory_keyword_or_non_ascii_text() {
+        let mut hdu = Hdu::new(&[2], vec![1.0f32, 2.0]);
+        hdu.insert("OBJECT", "M31");
+        for key in &[
+            "SIMPLE", "BITPIX", "NAXIS", "NAXIS1", "NAXIS2", "PCOUNT", "END",
+        ] {
+            match hdu.try_insert(key, 8) {
+                Err(FitsError::InvalidKeyword { keyword, .. }) => assert_eq!(keyword, *key),
+                other => panic!("Should be InvalidKeyword! Got {:?}", other),
+            }
+            assert!(hdu.insert_before("OBJECT", key, 8).is_err());
+            assert!(hdu.insert_after("OBJECT", key, 8).is_err());
+        }
+        assert_eq!(hdu.value("BITPIX"), Some(&HeaderValue::IntegerNumber(-32)));
+        assert_eq!(hdu.value("NAXIS2"), None);
+
+        let error = hdu.try_insert("OBSERVER", "Édouard").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Text of keyword OBSERVER is not printable ASCII"
+        );
+        assert!(hdu.insert_after("OBJECT", "OBSERVER", "A\tB").is_err());
+        assert!(hdu.set_comment("OBJECT", "Galaxie d'Andromède").is_err());
+        assert!(hdu.add_comment("\u{2605}").is_err());
+        assert!(hdu.add_history("\n").is_err());
+        assert_eq!(hdu.value("OBSERVER"), None);
+        assert_eq!(hdu.comment("OBJECT"), None);
+        assert!(hdu.comments().is_empty());
+        assert!(hdu.history().is_empty());
+
+        // XTENSION sets the type of an extension yet to be written
+        hdu.insert("XTENSION", "IMAGE");
+        let fits = Fits::open("test/testprog.fit").unwrap();
+        let mut hdu = fits.into_iter().nth(1).unwrap();
+        assert!(hdu.try_insert("XTENSION", "IMAGE").is_err());
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid value for keyword NAXIS")]
+    fn insert_mandatory_keyword_panics() {
+        Hdu::new(&[2], vec![1.0f32, 2.0]).insert("NAXIS", 2);
+    }
+
+    #[test]
+    fn insert_among_mandatory_keywords() {
+        let mut hdu = Hdu::new(&[2], vec![1.0f32, 2.0]);
+        match hdu.insert_after("SIMPLE", "OBJECT", "M31") {
+            Err(FitsError::InvalidKeyword { keyword, .. }) => assert_eq!(keyword, "SIMPLE"),
+            other => panic!("Should be InvalidKeyword! Got {:?}", other),
+        }
+        assert!(hdu.insert_before("NAXIS1", "OBJECT", "M31").is_err());
+        assert_eq!(hdu.value("OBJECT"), None);
+        hdu.insert_after("NAXIS1", "OBJECT", "M31").unwrap();
+        assert!(hdu.insert_before("OBJECT", "EXPTIME", 10).is_ok());
+        assert!(hdu.insert_after("BITPIX", "OBJECT", "M31").is_err());
+        assert_eq!(hdu.position("OBJECT"), Some(5));
+
+        let fits = Fits::open("test/testprog.fit").unwrap();
+        let mut hdu = fits.into_iter().nth(4).unwrap();
+        assert!(hdu.insert_after("GCOUNT", "OBJECT", "M31").is_err());
+        assert!(hdu.insert_before("TFIELDS", "OBJECT", "M31").is_err());
+        hdu.insert_after("TFIELDS", "OBJECT", "M31").unwrap();
+        assert_eq!(
+            hdu.position("OBJECT"),
+            Some(hdu.position("TFIELDS").unwrap() + 1)
+        );
+    }
+
+    #[test]
+    fn write_edited_header() {
+        let path = temp_path("write_edited_header.fits");
+        let mut fits = Fits::create(&path, Hdu::new(&[2], vec![1.0f32, 2.0])).unwrap();
+        let mut hdu = Hdu::new(&[2], vec![1i32, 2]);
+        hdu.insert("EXTNAME", "MASK");
+        hdu.insert_before("EXTNAME", "INHERIT", true).unwrap();
+        hdu.set_comment("EXTNAME", "Name of extension").unwrap();
+        hdu.add_comment(&"x".repeat(100)).unwrap();
+        fits.push(hdu).unwrap();
+        drop(fits);
+
+        let bytes = fs::read(&path).unwrap();
+        let cards: Vec<_> = bytes[2 * 2880..3 * 2880]
+            .chunks(80)
+            .map(|card| String::from_utf8(card.to_vec()).unwrap())
+            .collect();
+        assert_eq!(cards[0], format!("{:<80}", "XTENSION= 'IMAGE   '"));
+        assert_eq!(
+            cards[1],
+            format!("{:<80}", "BITPIX  =                   32")
+        );
+        assert_eq!(
+            cards[6],
+            format!("{:<80}", "INHERIT =                    T")
+        );
+        assert_eq!(
+            cards[7],
+            format!("{:<80}", "EXTNAME = 'MASK    ' / Name of extension")
+        );
+        assert_eq!(cards[8], format!("COMMENT {}", "x".repeat(72)));
+        assert_eq!(
+            cards[9],
+            format!("{:<80}", format!("COMMENT {}", "x".repeat(28)))
+        );
+        assert_eq!(cards[10], format!("{:<80}", "END"));
+
+        let fits = Fits::open(&path).unwrap();
+        assert_eq!(fits[1].comments(), ["x".repeat(72), "x".repeat(28)]);
+        fs::remove_file(&path).unwrap();
+    }
+
+    /// Debug representation of the data of all HDUs of `fits`.
+    fn debug_data(fits: &Fits) -> Vec<String> {
+        fits.iter()
+            .map(|hdu| {
+                format!(
+                    "{:?} {:?} {:?}",
+                    hdu.read_data(),
+                    hdu.read_bintable(),
+                    hdu.read_ascii_table()
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn update_header_in_place() {
+        let path = temp_path("update_header_in_place.fits");
+        fs::copy("test/testprog.fit", &path).unwrap();
+        let original = debug_data(&Fits::open("test/testprog.fit").unwrap());
+        let mut fits = Fits::open_mut(&path).unwrap();
+        fits[0].insert("OBJECT", "NGC 1");
+        fits[0].insert("KY_IKYJ", 52);
+        fits[3].remove("TTYPE1").unwrap();
+        fits.flush().unwrap();
+        drop(fits);
+
+        let length = fs::metadata("test/testprog.fit").unwrap().len();
+        assert_eq!(fs::metadata(&path).unwrap().len(), length);
+        let fits = Fits::open(&path).unwrap();
+        assert_eq!(fits[0].value("OBJECT"), Some(&HeaderValue::from("NGC 1")));
+        assert_eq!(
+            fits[0].value("KY_IKYJ"),
+            Some(&HeaderValue::IntegerNumber(52))
+        );
+        assert!(fits[3].value("TTYPE1").is_none());
+        assert_eq!(fits.iter().count(), 8);
+        let mut data = debug_data(&fits);
+        // Removing TTYPE1 unnamed the first column of the ASCII table
+        data[3] = data[3].replace("name: None", "name: Some(\"Name\")");
+        assert_eq!(data, original);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn update_header_growing() {
+        let path = temp_path("update_header_growing.fits");
+        fs::copy("test/testprog.fit", &path).unwrap();
+        let original = debug_data(&Fits::open("test/testprog.fit").unwrap());
+        let mut fits = Fits::open_mut(&path).unwrap();
+        // Load the data of the shifted HDUs before editing
+        assert_eq!(debug_data(&fits), original);
+        for i in 0..40 {
+            fits[2].add_history(&format!("Step {}", i)).unwrap();
+        }
+        fits[5].add_comment("Last").unwrap();
+        fits.flush().unwrap();
+        assert_eq!(fits[2].history().len(), 40);
+        assert_eq!(debug_data(&fits), original);
+        drop(fits);
+
+        let length = fs::metadata("test/testprog.fit").unwrap().len();
+        assert_eq!(fs::metadata(&path).unwrap().len(), length + 2880);
+        let fits = Fits::open(&path).unwrap();
+        assert_eq!(fits[2].history()[39], "Step 39");
+        assert_eq!(fits[5].comments(), ["Last"]);
+        assert_eq!(fits.iter().count(), 8);
+        assert_eq!(debug_data(&fits), original);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn update_data_in_place() {
+        let path = temp_path("update_data_in_place.fits");
+        fs::copy("test/testprog.fit", &path).unwrap();
+        let original = debug_data(&Fits::open("test/testprog.fit").unwrap());
+        let mut fits = Fits::open_mut(&path).unwrap();
+        match fits[0].read_data_mut() {
+            FitsData::IntegersI32(array) => {
+                array.data[0] = Some(1234);
+                array.data[19] = None;
+            }
+            _ => panic!("Should be IntegersI32!"),
+        }
+        fits.flush().unwrap();
+        drop(fits);
+
+        let length = fs::metadata("test/testprog.fit").unwrap().len();
+        assert_eq!(fs::metadata(&path).unwrap().len(), length);
+        let fits = Fits::open(&path).unwrap();
+        match fits[0].read_data() {
+            FitsData::IntegersI32(array) => {
+                assert_eq!(array.shape, [10, 2]);
+                assert_eq!(array.data[0], Some(1234));
+                assert_eq!(array.data[1], Some(2));
+                assert_eq!(array.data[19], None);
+            }
+            _ => panic!("Should be IntegersI32!"),
+        }
+        assert_eq!(debug_data(&fits)[1..], original[1..]);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn update_data_after_growing_header() {
+        let path = temp_path("update_data_after_growing_header.fits");
+        fs::copy("test/testprog.fit", &path).unwrap();
+        let mut fits = Fits::open_mut(&path).unwrap();
+        for i in 0..100 {
+            fits[0].add_history(&format!("Step {}", i)).unwrap();
+        }
+        let expected = match fits[2].read_data_mut() {
+            FitsData::FloatingPoint32(array) => {
+                for n in array.data.iter_mut() {
+                    *n *= 2.0;
+                }
+                array.data.clone()
+            }
+            _ => panic!("Should be FloatingPoint32!"),
+        };
+        fits.flush().unwrap();
+        drop(fits);
+
+        let fits = Fits::open(&path).unwrap();
+        assert_eq!(fits[0].history().len(), 101);
+        match fits[2].read_data() {
+            FitsData::FloatingPoint32(array) => assert_eq!(array.data, expected),
+            _ => panic!("Should be FloatingPoint32!"),
+        }
+        assert_eq!(
+            fits[7].value("EXTNAME"),
+            Some(&HeaderValue::from("new_table"))
+        );
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn update_data_mismatch() {
+        let path = temp_path("update_data_mismatch.fits");
+        fs::copy("test/testprog.fit", &path).unwrap();
+        let mut fits = Fits::open_mut(&path).unwrap();
+        fits[0].insert("OBJECT", "Unchanged");
+        *fits[2].read_data_mut() =
+            FitsData::FloatingPoint32(FitsDataArray::new(&[2], vec![1.0, 2.0]));
+        match fits.flush() {
+            Err(FitsError::DataMismatch { hdu: Some(2) }) => (),
+            other => panic!("Should be DataMismatch! Got {:?}", other),
+        }
+        *fits[2].read_data_mut() =
+            FitsData::FloatingPoint64(FitsDataArray::new(&[15, 25], vec![0.0; 15 * 25]));
+        assert!(fits.flush().is_err());
+        drop(fits);
+
+        assert_eq!(
+            fs::read(&path).unwrap(),
+            fs::read("test/testprog.fit").unwrap()
+        );
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn update_data_without_blank() {
+        let path = temp_path("update_data_without_blank.fits");
+        Fits::create(&path, Hdu::new(&[2], vec![1i32, 2])).unwrap();
+        let written = fs::read(&path).unwrap();
+        let mut fits = Fits::open_mut(&path).unwrap();
+        fits[0].insert("OBJECT", "Unchanged");
+        match fits[0].read_data_mut() {
+            FitsData::IntegersI32(array) => array.data[1] = None,
+            _ => panic!("Should be IntegersI32!"),
+        }
+        match fits.flush() {
+            Err(FitsError::MissingKeyword {
+                hdu: Some(0),
+                keyword,
+            }) => assert_eq!(keyword, "BLANK"),
+            other => panic!("Should be MissingKeyword! Got {:?}", other),
+        }
+        drop(fits);
+
+        assert_eq!(fs::read(&path).unwrap(), written);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn update_data_out_of_bitpix_range() {
+        let path = temp_path("update_data_out_of_bitpix_range.fits");
+        let cards = [
+            "SIMPLE  =                    T",
+            "BITPIX  =                   16",
+            "NAXIS   =                    1",
+            "NAXIS1  =                    2",
+            "END",
+        ];
+        write_header_only(&path, &cards, 1);
+        let written = fs::read(&path).unwrap();
+        let mut fits = Fits::open_mut(&path).unwrap();
+        match fits[0].read_data_mut() {
+            FitsData::IntegersI32(array) => array.data[1] = Some(100_000),
+            _ => panic!("Should be IntegersI32!"),
+        }
+        match fits.flush() {
+            Err(FitsError::DataMismatch { hdu }) => assert_eq!(hdu, Some(0)),
+            other => panic!("Should be DataMismatch! Got {:?}", other),
+        }
+        assert_eq!(fs::read(&path).unwrap(), written);
+        match fits[0].read_data_mut() {
+            FitsData::IntegersI32(array) => array.data[1] = Some(-32768),
+            _ => panic!("Should be IntegersI32!"),
+        }
+        fits.flush().unwrap();
+        drop(fits);
+        let fits = Fits::open(&path).unwrap();
+        match fits[0].read_data() {
+            FitsData::IntegersI32(array) => assert_eq!(array.data, vec![Some(0), Some(-32768)]),
+            _ => panic!("Should be IntegersI32!"),
+        }
+        drop(fits);
+
+        let cards = [
+            "SIMPLE  =                    T",
+            "BITPIX  =                    8",
+            "NAXIS   =                    1",
+            "NAXIS1  =                    2",
+            "BLANK   =                  300",
+            "END",
+        ];
+        write_header_only(&path, &cards, 1);
+        let written = fs::read(&path).unwrap();
+        let mut fits = Fits::open_mut(&path).unwrap();
+        match fits[0].read_data_mut() {
+            FitsData::IntegersU8(array) => array.data[1] = None,
+            _ => panic!("Should be IntegersU8!"),
+        }
+        match fits.flush() {
+            Err(FitsError::InvalidKeyword { keyword, .. }) => assert_eq!(keyword, "BLANK"),
+            other => panic!("Should be InvalidKeyword! Got {:?}", other),
+        }
+        drop(fits);
+
+        assert_eq!(fs::read(&path).unwrap(), written);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn update_table_data() {
+        let path = temp_path("update_table_data.fits");
+        fs::copy("test/testprog.fit", &path).unwrap();
+        let original = debug_data(&Fits::open("test/testprog.fit").unwrap());
+        let mut fits = Fits::open_mut(&path).unwrap();
+        for &i in &[3, 4, 5] {
+            match fits[i].try_read_data_mut() {
+                Err(FitsError::NotAnImage { hdu }) => assert_eq!(hdu, Some(i)),
+                other => panic!("Should be NotAnImage! Got {:?}", other),
+            }
+        }
+        fits[5].insert("OBJECT", "Heap");
+        fits.flush().unwrap();
+        drop(fits);
+
+        let fits = Fits::open(&path).unwrap();
+        assert_eq!(fits[5].value("OBJECT"), Some(&HeaderValue::from("Heap")));
+        assert_eq!(debug_data(&fits), original);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn read_from_reader() {
+        let bytes = fs::read("test/testprog.fit").unwrap();
+        let fits = Fits::from_reader(Cursor::new(bytes));
+        let original = debug_data(&Fits::open("test/testprog.fit").unwrap());
+        assert_eq!(debug_data(&fits), original);
+        assert_eq!(fits.iter().count(), 8);
+        assert_eq!(
+            fits["Test-ASCII"].value("XTENSION"),
+            Some(&HeaderValue::from("TABLE"))
+        );
+        let hdus: Vec<_> = fits.into_iter().collect();
+        assert_eq!(hdus.len(), 8);
+    }
+
+    #[test]
+    fn open_compressed_files() {
+        let expected = debug_data(&Fits::open("test/testprog.fit").unwrap());
+        for path in &[
+            "test/testprog.fit.gz",
+            "test/testprog.fit.bz2",
+            "test/testprog.fit.Z",
+        ] {
+            for decompress in &[Decompress::InMemory, Decompress::TempFile] {
+                let fits = Fits::open_with(path, *decompress).unwrap();
+                assert_eq!(fits.iter().count(), 8);
+                assert_eq!(debug_data(&fits), expected);
+            }
+            let mut fits = Fits::open(path).unwrap();
+            assert_eq!(
+                fits["Test-ASCII"].value("EXTNAME"),
+                fits[3].value("EXTNAME")
+            );
+            fits[0].insert("OBJECT", "M31");
+            assert!(fits.flush().is_err());
+        }
+    }
+
+    #[test]
+    fn open_mut_compressed_file() {
+        let error = Fits::open_mut("test/testprog.fit.gz").unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn from_reader_is_read_only() {
+        let bytes = fs::read("test/testprog.fit").unwrap();
+        let mut fits = Fits::from_reader(Cursor::new(bytes));
+        fits[0].insert("OBJECT", "M31");
+        assert!(fits.flush().is_err());
+        let error = fits.push(Hdu::empty()).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::Unsupported);
+    }
+
+    #[test]
+    fn edit_header_of_opened_file() {
+        let path = temp_path("edit_header_of_opened_file.fits");
+        fs::copy("test/testprog.fit", &path).unwrap();
+        let mut fits = Fits::open_mut(&path).unwrap();
+        for hdu in fits.iter_mut() {
+            hdu.add_history("Reprocessed").unwrap();
+        }
+        fits[0].rename("KY_IKYJ", "KY_NEW").unwrap();
+        assert_eq!(
+            fits[0].value("KY_NEW"),
+            Some(&HeaderValue::IntegerNumber(51))
+        );
+        assert!(fits[0].value("KY_IKYJ").is_none());
+        assert_eq!(
+            fits[0].history(),
+            ["this is the 6th template card", "Reprocessed"]
+        );
+        assert_eq!(fits[7].history(), ["Reprocessed"]);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn flush_keeps_unedited_cards() {
+        let path = temp_path("flush_keeps_unedited_cards.fits");
+        let long_comment = format!("LONGCOM = 1 / {}", "a long comment ".repeat(5));
+        let cards = [
+            "SIMPLE  =                    T",
+            "BITPIX  =                    8",
+            "NAXIS   =                    0",
+            "UNDEF   = / undefined value",
+            "BADVAL  = 2020-01-01",
+            &long_comment[..80],
+            "        Section of the header",
+            "",
+            "OBJECT  = 'M31     '           / target",
+            "",
+            "",
+            "END",
+        ];
+        write_header_only(&path, &cards, 0);
+        let mut fits = Fits::open_mut(&path).unwrap();
+        fits[0].insert("OBJECT", "M32");
+        fits[0].insert("EXPTIME", 10);
+        fits.flush().unwrap();
+        fits[0].set_comment("UNDEF", "still undefined").unwrap();
+        fits.flush().unwrap();
+        drop(fits);
+
+        let bytes = fs::read(&path).unwrap();
+        assert_eq!(bytes.len(), 2880);
+        let written: Vec<_> = bytes.chunks(80).map(String::from_utf8_lossy).collect();
+        assert_eq!(written[3], format!("{:<80}", "UNDEF   = / still undefined"));
+        for i in 4..8 {
+            assert_eq!(written[i], format!("{:<80}", cards[i]));
+        }
+        assert!(written[8].starts_with("OBJECT  = 'M32"));
+        assert!(written[8].trim_end().ends_with(" / target"));
+        assert!(written[9].starts_with("EXPTIME ="));
+        assert_eq!(written[10], " ".repeat(80));
+        assert!(written[11].starts_with("END "));
+
+        let fits = Fits::open(&path).unwrap();
+        let hdu = &fits[0];
+        assert_eq!(hdu.value("UNDEF"), None);
+        assert_eq!(hdu.comment("UNDEF"), Some("still undefined"));
+        assert_eq!(hdu.value("OBJECT"), Some(&HeaderValue::from("M32")));
+        let blank: Vec<_> = hdu.cards().filter(|&(key, _, _)| key.is_empty()).collect();
+        assert_eq!(
+            blank,
+            [
+                ("", None, Some("Section of the header")),
+                ("", None, None),
+                ("", None, None)
+            ]
+        );
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn flush_puts_mandatory_keywords_first() {
+        let path = temp_path("flush_puts_mandatory_keywords_first.fits");
+        let primary = [
+            "SIMPLE  =                    T",
+            "BITPIX  =                    8",
+            "NAXIS   =                    1",
+            "OBJECT  = 'M31     '",
+            "NAXIS1  =                    0",
+            "END",
+        ];
+        let table = [
+            "XTENSION= 'BINTABLE'",
+            "BITPIX  =                    8",
+            "NAXIS   =                    2",
+            "NAXIS1  =                    1",
+            "NAXIS2  =                    0",
+            "EXTNAME = 'TABLE   '",
+            "GCOUNT  =                    1",
+            "PCOUNT  =                    0",
+            "TFORM1  = 'B       '",
+            "TFIELDS =                    1",
+            "END",
+        ];
+        let mut bytes = Vec::new();
+        for cards in &[&primary[..], &table[..]] {
+            let header: String = cards.iter().map(|card| format!("{:<80}", card)).collect();
+            bytes.extend(format!("{:<2880}", header).into_bytes());
+        }
+        fs::write(&path, bytes).unwrap();
+        let mut fits = Fits::open_mut(&path).unwrap();
+        fits[0].insert("OBJECT", "M32");
+        fits[1].insert("EXTNAME", "EDITED");
+        fits.flush().unwrap();
+        drop(fits);
+
+        let fits = Fits::open(&path).unwrap();
+        let keys: Vec<_> = fits[0].cards().map(|(key, _, _)| key).collect();
+        assert_eq!(keys, ["SIMPLE", "BITPIX", "NAXIS", "NAXIS1", "OBJECT"]);
+        let keys: Vec<_> = fits[1].cards().map(|(key, _, _)| key).collect();
+        assert_eq!(
+            keys,
+            [
+                "XTENSION", "BITPIX", "NAXIS", "NAXIS1", "NAXIS2", "PCOUNT", "GCOUNT", "TFIELDS",
+                "EXTNAME", "TFORM1"
+            ]
+        );
+        assert_eq!(fits[1].value("EXTNAME"), Some(&HeaderValue::from("EDITED")));
+        assert_eq!(fits[1].read_bintable().unwrap().columns().len(), 1);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn read_first_hdu() {
+        let fits = Fits::open("test/testprog.fit").unwrap();
+        let mut iter = fits.into_iter();
+        let hdu = iter.next().unwrap();
+        assert_eq!(hdu.value("SIMPLE"), Some(&HeaderValue::Logical(true)));
+        assert_eq!(
+            hdu.value("CARD1"),
+            Some(&HeaderValue::CharacterString(String::from(
+                "12345678901234567890123456789012345678901234567890123456789012345678",
+            )))
+        );
+        assert_eq!(
+            hdu.value("CARD2"),
+            Some(&HeaderValue::CharacterString(String::from(
+                "1234567890123456789012345678901234567890123456789012345678901234'67",
+            )))
+        );
+        assert_eq!(
+            hdu.value("CARD3"),
+            Some(&HeaderValue::CharacterString(String::from(
+                "1234567890123456789012345678901234567890123456789012345678901234''",
+            )))
+        );
+        assert_eq!(hdu.value("KY_IKYJ"), Some(&HeaderValue::IntegerNumber(51)));
+        assert_eq!(
+            hdu.value("KY_IKYE"),
+            Some(&HeaderValue::RealFloatingNumber(-1.3346E+01))
+        );
+    }
+
+    #[test]
+    fn compute_hdu_data_byte_length() {
+        let fits = Fits::open("test/testprog.fit").unwrap();
+        let mut iter = fits.into_iter();
+        let primary_hdu = iter.next().unwrap();
+        assert_eq!(primary_hdu.data_byte_length().ok(), Some((32 / 8) * 10 * 2));
+    }
+
+    #[test]
+    fn compute_hdu_data_byte_length_with_heap() {
+        let fits = Fits::open("test/testprog.fit").unwrap();
+        // 20 rows of 80 bytes, followed by a heap of 4446 bytes
+        assert_eq!(fits[5].data_byte_length().ok(), Some(80 * 20 + 4446));
+    }
+
+    #[test]
+    fn read_hdu_after_heap() {
+        let fits = Fits::open("test/testprog.fit").unwrap();
+        let hdu = &fits[6];
+        assert_eq!(hdu.header[0].0, "XTENSION");
+        assert_eq!(hdu.value("XTENSION"), Some(&HeaderValue::from("IMAGE")));
+        assert_eq!(hdu.value("NAXIS"), Some(&HeaderValue::IntegerNumber(2)));
+        assert_eq!(
+            fits[7].value("EXTNAME"),
+            Some(&HeaderValue::from("new_table"))
+        );
+    }
+
+    #[test]
+    fn compute_random_groups_data_byte_length() {
+        let path = temp_path("compute_random_groups_data_byte_length.fits");
+        let cards = [
+            "SIMPLE  =                    T",
+            "BITPIX  =                  -32",
+            "NAXIS   =                    3",
+            "NAXIS1  =                    0",
+            "NAXIS2  =                    2",
+            "NAXIS3  =                    3",
+            "GROUPS  =                    T",
+            "PCOUNT  =                    2",
+            "GCOUNT  =                    4",
+            "END",
+        ];
+        write_header_only(&path, &cards, 1);
+
+        let mut fits = Fits::open_mut(&path).unwrap();
+        assert_eq!(fits[0].data_byte_length().ok(), Some(4 * 4 * (2 + 2 * 3)));
+        assert!(matches!(
+            fits[0].try_read_data_mut(),
+            Err(FitsError::NotAnImage { hdu: Some(0) })
+        ));
+        fits.push(Hdu::new(&[2], vec![7, 8])).unwrap();
+        drop(fits);
+
+        let fits = Fits::open(&path).unwrap();
+        assert_eq!(fits.iter().count(), 2);
+        match fits[1].read_data() {
+            FitsData::IntegersI32(array) => assert_eq!(array.data, vec![Some(7), Some(8)]),
+            _ => panic!("Should be IntegersI32!"),
+        }
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn try_iter_over_truncated_header() {
+        let path = temp_path("try_iter_over_truncated_header.fits");
+        let bytes = fs::read("test/testprog.fit").unwrap();
+        fs::write(&path, &bytes[..20160 + 1000]).unwrap();
+
+        let fits = Fits::open(&path).unwrap();
+        let hdus: Vec<_> = fits.try_iter().collect();
+        assert_eq!(hdus.len(), 3);
+        assert!(hdus[0].is_ok() && hdus[1].is_ok());
+        match hdus[2] {
+            Err(FitsError::TruncatedHeader { hdu, offset }) => {
+                assert_eq!(hdu, 2);
+                assert_eq!(offset, 20160);
+            }
+            _ => panic!("Should be TruncatedHeader!"),
+        }
+        assert!(fits.try_get(1).unwrap().is_some());
+        assert!(fits.try_get(3).is_err());
+        assert!(fits.try_get_by_name("Test-ASCII").is_err());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    #[should_panic(expected = "Header of HDU 2 at offset 20160 ends before END keyword")]
+    fn iter_over_truncated_header() {
+        let path = temp_path("iter_over_truncated_header.fits");
+        let bytes = fs::read("test/testprog.fit").unwrap();
+        fs::write(&path, &bytes[..20160 + 1000]).unwrap();
+        let fits = Fits::open(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        fits.iter().count();
+    }
+
+    #[test]
+    fn try_iter_mut_over_truncated_header() {
+        let path = temp_path("try_iter_mut_over_truncated_header.fits");
+        let bytes = fs::read("test/testprog.fit").unwrap();
+        fs::write(&path, &bytes[..20160 + 1000]).unwrap();
+
+        let mut fits = Fits::open(&path).unwrap();
+        {
+            let hdus: Vec<_> = fits.try_iter_mut().collect();
+            assert_eq!(hdus.len(), 3);
+            assert!(hdus[0].is_ok() && hdus[1].is_ok());
+            assert!(hdus[2].is_err());
+        }
+        fits.try_get_mut(1)
+            .unwrap()
+            .unwrap()
+            .insert("OBJECT", "M31");
+        assert_eq!(fits[1].value("OBJECT"), Some(&HeaderValue::from("M31")));
+        match fits.try_get_mut(2) {
+            Err(FitsError::TruncatedHeader { hdu, offset }) => {
+                assert_eq!(hdu, 2);
+                assert_eq!(offset, 20160);
+            }
+            _ => panic!("Should be TruncatedHeader!"),
+        }
+        assert!(fits.try_get_mut_by_name("Test-ASCII").is_err());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn try_get_mut_by_name() {
+        let mut fits = Fits::open("test/testprog.fit").unwrap();
+        fits.try_get_mut_by_name("Test-ASCII")
+            .unwrap()
+            .unwrap()
+            .insert("OBJECT", "M31");
+        assert_eq!(
+            fits["Test-ASCII"].value("OBJECT"),
+            Some(&HeaderValue::from("M31"))
+        );
+        assert!(fits.try_get_mut_by_name("NOTFOUND").unwrap().is_none());
+    }
+
+    #[test]
+    fn try_read_truncated_data() {
+        let path = temp_path("try_read_truncated_data.fits");
+        let bytes = fs::read("test/testprog.fit").unwrap();
+        fs::write(&path, &bytes[..8640 + 40]).unwrap();
+
+        let fits = Fits::open(&path).unwrap();
+        assert_eq!(fits.iter().count(), 1);
+        match fits[0].try_read_data() {
+            Err(FitsError::Io { offset, .. }) => assert_eq!(offset, Some(8640)),
+            _ => panic!("Should be an I/O error!"),
+        }
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn try_iter_over_unsupported_bitpix() {
+        let path = temp_path("try_iter_over_unsupported_bitpix.fits");
+        let cards = [
+            "SIMPLE  =                    T",
+            "BITPIX  =                   12",
+            "NAXIS   =                    0",
+            "END",
+        ];
+        write_header_only(&path, &cards, 0);
+
+        let fits = Fits::open(&path).unwrap();
+        match fits.try_get(0) {
+            Err(FitsError::UnsupportedBitpix { hdu, bitpix }) => {
+                assert_eq!(hdu, Some(0));
+                assert_eq!(bitpix, 12);
+            }
+            _ => panic!("Should be UnsupportedBitpix!"),
+        }
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn try_iter_over_missing_naxis() {
+        let path = temp_path("try_iter_over_missing_naxis.fits");
+        let cards = [
+            "SIMPLE  =                    T",
+            "BITPIX  =                    8",
+            "NAXIS   =                    2",
+            "NAXIS1  =                   10",
+            "END",
+        ];
+        write_header_only(&path, &cards, 0);
+
+        let fits = Fits::open(&path).unwrap();
+        let error = fits.try_iter().next().unwrap().unwrap_err();
+        assert_eq!(error.to_string(), "Missing keyword NAXIS2 in HDU 0");
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn read_card_image_64_bit_integer() {
+        let card = CardImage::from("NAXIS2  =           3000000000");
+        let value_comment = card.to_header_key_value().1.unwrap();
+        assert_eq!(
+            value_comment.value,
+            Some(HeaderValue::IntegerNumber(3_000_000_000))
+        );
+
+        let card = CardImage::from("DATASUM = -9223372036854775808");
+        let value_comment = card.to_header_key_value().1.unwrap();
+        assert_eq!(
+            value_comment.value,
+            Some(HeaderValue::IntegerNumber(i64::MIN))
+        );
+    }
+
+    #[test]
+    fn compute_data_byte_length_over_4_gib() {
+        let path = temp_path("compute_data_byte_length_over_4_gib.fits");
+        let cards = [
+            "SIMPLE  =                    T",
+            "BITPIX  =                   16",
+            "NAXIS   =                    2",
+            "NAXIS1  =                    2",
+            "NAXIS2  =           3000000000",
+            "END",
+        ];
+        write_header_only(&path, &cards, 0);
+
+        let fits = Fits::open(&path).unwrap();
+        assert_eq!(fits[0].data_length().ok(), Some(6_000_000_000));
+        assert_eq!(fits[0].data_byte_length().ok(), Some(12_000_000_000));
+        assert_eq!(
+            fits[0].next_hdu_position(),
+            2880 + 12_000_000_000u64.div_ceil(2880) * 2880
+        );
+        // The file ends after the header: no data and no other HDU are read
+        assert_eq!(fits.iter().count(), 1);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn try_iter_over_overflowing_data_length() {
+        let path = temp_path("try_iter_over_overflowing_data_length.fits");
+        let cards = [
+            "SIMPLE  =                    T",
+            "BITPIX  =                  -64",
+            "NAXIS   =                    3",
+            "NAXIS1  =           3000000000",
+            "NAXIS2  =           3000000000",
+            "NAXIS3  =           3000000000",
+            "END",
+        ];
+        write_header_only(&path, &cards, 0);
+
+        let fits = Fits::open(&path).unwrap();
+        let error = fits.try_iter().next().unwrap().unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Invalid value for keyword NAXIS in HDU 0"
+        );
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn try_iter_over_negative_pcount() {
+        let path = temp_path("try_iter_over_negative_pcount.fits");
+        let cards = [
+            "XTENSION= 'BINTABLE'",
+            "BITPIX  =                    8",
+            "NAXIS   =                    2",
+            "NAXIS1  =                    4",
+            "NAXIS2  =                    2",
+            "PCOUNT  =                   -8",
+            "GCOUNT  =                    1",
+            "END",
+        ];
+        write_header_only(&path, &cards, 1);
+
+        let fits = Fits::open(&path).unwrap();
+        let error = fits.try_iter().next().unwrap().unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Invalid value for keyword PCOUNT in HDU 0"
+        );
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn try_read_invalid_section() {
+        let fits = Fits::open("test/testprog.fit").unwrap();
+        match fits[0].try_read_section(&[0], &[1], &[1]) {
+            Err(FitsError::InvalidSection(_)) => (),
+            _ => panic!("Should be InvalidSection!"),
+        }
+        assert!(fits[0]
+            .try_read_section(&[0, 0], &[10, 2], &[0, 1])
+            .is_err());
+    }
+
+    #[test]
+    fn iterate_over_hdu() {
+        let fits = Fits::open("test/testprog.fit").unwrap();
+        let mut iter = fits.into_iter();
+        let primary_hdu = iter.next().unwrap();
+        assert_eq!(primary_hdu.header[0].0, "SIMPLE");
+        let hdu2 = iter.next().unwrap();
+        assert_eq!(hdu2.header[0].0, "XTENSION");
+        assert_eq!(
+            hdu2.value("XTENSION").unwrap(),
+            &HeaderValue::CharacterString(String::from("BINTABLE"))
+        );
+        let hdu3 = iter.next().unwrap();
+        assert_eq!(hdu3.header[0].0, "XTENSION");
+        assert_eq!(
+            hdu3.value("XTENSION").unwrap(),
+            &HeaderValue::CharacterString(String::from("IMAGE"))
+        );
+    }
+
+    #[test]
+    fn iterate_over_all_hdus() {
+        let fits = Fits::open("test/testprog.fit").unwrap();
+        assert_eq!(fits.into_iter().count(), 8);
+    }
+
+    #[test]
+    fn make_primary_hdu_array() {
+        let fits = Fits::open("test/testprog.fit").unwrap();
+        let mut iter = fits.into_iter();
+        let primary_hdu = iter.next().unwrap();
+        let data = primary_hdu.read_data();
+        match data {
+            &FitsData::IntegersI32(ref array) => {
+                assert_eq!(array.shape, vec![10, 2]);
+                assert_eq!(
+                    array.data,
+                    vec![
+                        None,
+                        Some(2),
+                        Some(3),
+                        None,
+                        Some(5),
+                        Some(6),
+                        Some(7),
+                        None,
+                        Some(9),
+                        Some(10),
+                        Some(11),
+                        None,
+                        Some(13),
+                        Some(14),
+                        Some(15),
+                        None,
+                        Some(17),
+                        Some(18),
+                        Some(19),
+                        None,
+                    ]
+                );
+            }
+            _ => panic!("Should be IntegersI32!"),
+        }
+    }
+
+    #[test]
+    fn read_second_hdu_array() {
+        // Raw data of a binary table. Use `Hdu::read_bintable` to read columns.
+        let fits = Fits::open("test/testprog.fit").unwrap();
+        let mut iter = fits.into_iter();
+        iter.next();
+        let table_hdu_1 = iter.next().unwrap();
+        let data = table_hdu_1.read_data();
+        match data {
+            FitsData::IntegersU8(array) => {
+                assert_eq!(array.shape, vec![61, 20]);
+                let mut expected = [Some(0); 30];
+                expected[19] = Some(0x80);
+                expected[21..25].copy_from_slice(&[Some(0xff); 4]);
+                assert_eq!(&array.data[..30], &expected[..]);
+            }
+            _ => panic!("Should be IntegersU8!"),
+        }
+    }
+
+    #[test]
+    fn iterate_over_hdu_no_consume() {
+        let fits = Fits::open("test/testprog.fit").unwrap();
+        let mut iter = fits.iter();
+        let primary_hdu = iter.next().unwrap();
+        assert_eq!(primary_hdu.header[0].0, "SIMPLE");
+        let hdu2 = iter.next().unwrap();
+        assert_eq!(hdu2.header[0].0, "XTENSION");
         assert_eq!(
-            value_comment.value,
-            Some(HeaderValue::CharacterString(String::from(" ")))
+            hdu2.value("XTENSION").unwrap(),
+            &HeaderValue::CharacterString(String::from("BINTABLE"))
+        );
+        let hdu3 = iter.next().unwrap();
+        assert_eq!(hdu3.header[0].0, "XTENSION");
+        assert_eq!(
+            hdu3.value("XTENSION").unwrap(),
+            &HeaderValue::CharacterString(String::from("IMAGE"))
         );
     }
 
     #[test]
-    fn read_card_image_character_logical_true() {
-        let card = CardImage::from("SIMPLE  =                    T /                     ");
-        let header_key_value = card.to_header_key_value().unwrap();
-        let value_comment = header_key_value.1.unwrap();
-        assert_eq!(value_comment.value, Some(HeaderValue::Logical(true)));
+    fn iterate_over_hdu_mut() {
+        let mut fits = Fits::open("test/testprog.fit").unwrap();
+        let mut iter = fits.iter_mut();
+        let primary_hdu = iter.next().unwrap();
+        assert_eq!(primary_hdu.header[0].0, "SIMPLE");
+        let hdu2 = iter.next().unwrap();
+        assert_eq!(hdu2.header[0].0, "XTENSION");
+        assert_eq!(
+            hdu2.value("XTENSION").unwrap(),
+            &HeaderValue::CharacterString(String::from("BINTABLE"))
+        );
+        let hdu3 = iter.next().unwrap();
+        assert_eq!(hdu3.header[0].0, "XTENSION");
+        assert_eq!(
+            hdu3.value("XTENSION").unwrap(),
+            &HeaderValue::CharacterString(String::from("IMAGE"))
+        );
     }
 
     #[test]
-    fn read_card_image_character_logical_false() {
-        let card = CardImage::from("SIMPLE  =                    F /                     ");
-        let header_key_value = card.to_header_key_value().unwrap();
-        let value_comment = header_key_value.1.unwrap();
-        assert_eq!(value_comment.value, Some(HeaderValue::Logical(false)));
+    fn iterate_over_hdu_mut_twice() {
+        let mut fits = Fits::open("test/testprog.fit").unwrap();
+        {
+            let mut iter = fits.iter_mut();
+            let primary_hdu = iter.next().unwrap();
+            assert_eq!(primary_hdu.header[0].0, "SIMPLE");
+            let hdu2 = iter.next().unwrap();
+            assert_eq!(hdu2.header[0].0, "XTENSION");
+            assert_eq!(
+                hdu2.value("XTENSION").unwrap(),
+                &HeaderValue::CharacterString(String::from("BINTABLE"))
+            );
+            let hdu3 = iter.next().unwrap();
+            assert_eq!(hdu3.header[0].0, "XTENSION");
+            assert_eq!(
+                hdu3.value("XTENSION").unwrap(),
+                &HeaderValue::CharacterString(String::from("IMAGE"))
+            );
+        }
+        {
+            let mut iter = fits.iter_mut();
+            let primary_hdu = iter.next().unwrap();
+            assert_eq!(primary_hdu.header[0].0, "SIMPLE");
+            let hdu2 = iter.next().unwrap();
+            assert_eq!(hdu2.header[0].0, "XTENSION");
+            assert_eq!(
+                hdu2.value("XTENSION").unwrap(),
+                &HeaderValue::CharacterString(String::from("BINTABLE"))
+            );
+            let hdu3 = iter.next().unwrap();
+            assert_eq!(hdu3.header[0].0, "XTENSION");
+            assert_eq!(
+                hdu3.value("XTENSION").unwrap(),
+                &HeaderValue::CharacterString(String::from("IMAGE"))
+            );
+        }
     }
 
     #[test]
-    fn read_card_image_character_integer() {
-        let card = CardImage::from("BITPIX  =                    8 /                     ");
-        let header_key_value = card.to_header_key_value().unwrap();
-        let value_comment = header_key_value.1.unwrap();
-        assert_eq!(value_comment.value, Some(HeaderValue::IntegerNumber(8)));
+    fn index_over_fits() {
+        let fits = Fits::open("test/testprog.fit").unwrap();
+        let hdu2 = &fits[1];
+        assert_eq!(hdu2.header[0].0, "XTENSION");
+        assert_eq!(
+            hdu2.value("XTENSION").unwrap(),
+            &HeaderValue::CharacterString(String::from("BINTABLE"))
+        );
     }
 
     #[test]
-    fn read_card_image_character_real() {
-        let card =
-            CardImage::from("EXPTIME =              13501.5 / Total exposure time (seconds)");
-        let header_key_value = card.to_header_key_value().unwrap();
-        let value_comment = header_key_value.1.unwrap();
+    #[should_panic]
+    fn index_overflow_over_fits() {
+        let fits = Fits::open("test/testprog.fit").unwrap();
+        let _hdu2 = &fits[10];
+    }
+
+    #[test]
+    fn index_with_string_over_fits() {
+        let fits = Fits::open("test/testprog.fit").unwrap();
+        let hdu2 = &fits["Test-ASCII"];
+        assert_eq!(hdu2.header[0].0, "XTENSION");
         assert_eq!(
-            value_comment.value,
-            Some(HeaderValue::RealFloatingNumber(13501.5))
+            hdu2.value("XTENSION").unwrap(),
+            &HeaderValue::CharacterString(String::from("TABLE"))
         );
     }
 
     #[test]
-    fn read_card_image_character_real_exp() {
-        let card = CardImage::from("CDELT1  =      -1.666667E-03 /");
-        let header_key_value = card.to_header_key_value().unwrap();
-        let value_comment = header_key_value.1.unwrap();
+    #[should_panic]
+    fn index_with_string_not_found_over_fits() {
+        let fits = Fits::open("test/testprog.fit").unwrap();
+        let _hdu2 = &fits["FOOBAR"];
+    }
+
+    #[test]
+    fn write_card_image_integer() {
+        let card = CardImage::from_key_value(
+            "BITPIX",
+            Some(&HeaderValue::IntegerNumber(-32)),
+            Some(&String::from("number of bits")),
+        )
+        .unwrap();
         assert_eq!(
-            value_comment.value,
-            Some(HeaderValue::RealFloatingNumber(-1.666667E-03))
+            &card.0[..],
+            &CardImage::from("BITPIX  =                  -32 / number of bits").0[..]
         );
     }
 
     #[test]
-    fn read_first_hdu() {
+    fn write_card_image_character_string() {
+        let card = CardImage::from_key_value(
+            "AUTHOR",
+            Some(&HeaderValue::CharacterString(String::from("O'Hara"))),
+            None,
+        )
+        .unwrap();
+        assert_eq!(&card.0[..], &CardImage::from("AUTHOR  = 'O''Hara '").0[..]);
+        let header_key_value = card.to_header_key_value();
+        assert_eq!(
+            header_key_value.1.unwrap().value,
+            Some(HeaderValue::CharacterString(String::from("O'Hara")))
+        );
+    }
+
+    #[test]
+    fn write_card_image_empty_string() {
+        let card = CardImage::from_key_value("EMPTY", Some(&HeaderValue::from("")), None).unwrap();
+        assert_eq!(&card.0[..], &CardImage::from("EMPTY   = ''").0[..]);
+        let header_key_value = card.to_header_key_value();
+        assert_eq!(
+            header_key_value.1.unwrap().value,
+            Some(HeaderValue::from(""))
+        );
+
+        let path = temp_path("write_card_image_empty_string.fits");
+        let mut hdu = Hdu::new(&[1], vec![1.0f32]);
+        hdu.insert("EMPTY", "");
+        Fits::create(&path, hdu).unwrap();
+        let fits = Fits::open(&path).unwrap();
+        assert_eq!(fits[0].value("EMPTY"), Some(&HeaderValue::from("")));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn write_card_image_real() {
+        for n in &[13501.5, -1.666667E-03, 1e20, 3.0] {
+            let card = CardImage::from_key_value(
+                "EXPTIME",
+                Some(&HeaderValue::RealFloatingNumber(*n)),
+                None,
+            )
+            .unwrap();
+            let header_key_value = card.to_header_key_value();
+            assert_eq!(
+                header_key_value.1.unwrap().value,
+                Some(HeaderValue::RealFloatingNumber(*n))
+            );
+        }
+    }
+
+    #[test]
+    fn read_long_string() {
         let fits = Fits::open("test/testprog.fit").unwrap();
-        let mut iter = fits.into_iter();
-        let hdu = iter.next().unwrap();
-        assert_eq!(hdu.value("SIMPLE"), Some(&HeaderValue::Logical(true)));
+        let hdu = &fits[0];
         assert_eq!(
-            hdu.value("CARD1"),
-            Some(&HeaderValue::CharacterString(String::from(
-                "12345678901234567890123456789012345678901234567890123456789012345678",
-            )))
+            hdu.value("KEY_PKLS"),
+            Some(&HeaderValue::from(
+                "This is a very long string value that is continued over more than one keyword."
+            ))
         );
+        assert_eq!(hdu.comment("KEY_PKLS"), Some("fxpkls comment"));
+        assert!(hdu.value("CONTINUE").is_none());
+        assert!(!hdu.header.iter().any(|line| line.0 == "CONTINUE"));
+    }
+
+    #[test]
+    fn write_long_string() {
+        let value = "It's a string of more than 68 characters, with 'quotes', which needs \
+                     to be continued over several card images. "
+            .repeat(2);
+        let cards =
+            CardImage::from_long_string("LONG", &value, Some(&String::from("comment"))).unwrap();
+        // The last card holds an empty string to keep the trailing space
+        assert_eq!(cards.len(), 5);
+        assert!(cards[0].0.starts_with(b"LONG    = 'It''s a string"));
+        assert!(cards[1].0.starts_with(b"CONTINUE  '"));
+        for card in &cards[..4] {
+            let card = String::from_utf8(card.0.to_vec()).unwrap();
+            assert!(card.trim_end().ends_with("&'"));
+        }
         assert_eq!(
-            hdu.value("CARD2"),
-            Some(&HeaderValue::CharacterString(String::from(
-                "1234567890123456789012345678901234567890123456789012345678901234'67",
-            )))
+            &cards[4].0[..],
+            &CardImage::from("CONTINUE  '' / comment").0[..]
         );
+        let value_comment = read_long_string_cards(&cards);
+        assert_eq!(value_comment.value, Some(HeaderValue::from(value)));
+        assert_eq!(value_comment.comment, Some(String::from("comment")));
+    }
+
+    /// Read `cards` holding a single long string keyword.
+    fn read_long_string_cards(cards: &[CardImage]) -> super::HeaderValueComment {
+        let header: Vec<_> = cards
+            .iter()
+            .map(|card| (card.to_header_key_value(), card.clone()))
+            .collect();
+        let mut header = super::merge_long_strings(header);
+        assert_eq!(header.len(), 1);
+        header.remove(0).line.1.unwrap()
+    }
+
+    #[test]
+    fn write_long_string_with_long_comment() {
+        let value = "x".repeat(100);
+        let comment = "A long comment ".repeat(12).trim_end().to_owned();
+        let cards = CardImage::from_long_string("LONG", &value, Some(&comment)).unwrap();
+        assert_eq!(cards.len(), 5);
+        assert!(cards[1]
+            .0
+            .ends_with(b"&' / A long comment A long comment A"));
+        assert!(cards[2].0.starts_with(b"CONTINUE  '&' / long comment"));
+        assert!(cards[3].0.starts_with(b"CONTINUE  '&' / comment"));
         assert_eq!(
-            hdu.value("CARD3"),
-            Some(&HeaderValue::CharacterString(String::from(
-                "1234567890123456789012345678901234567890123456789012345678901234''",
-            )))
+            &cards[4].0[..],
+            &CardImage::from("CONTINUE  '' / comment A long comment").0[..]
+        );
+        let value_comment = read_long_string_cards(&cards);
+        assert_eq!(value_comment.value, Some(HeaderValue::from(value)));
+        assert_eq!(value_comment.comment, Some(comment));
+    }
+
+    #[test]
+    fn write_long_hierarch_string() {
+        let value = "A long string value of a HIERARCH keyword ".repeat(3);
+        let cards = CardImage::from_long_string("HIERARCH ESO OBS NAME", &value, None).unwrap();
+        assert_eq!(cards.len(), 4);
+        assert!(cards[0].0.starts_with(b"HIERARCH ESO OBS NAME = 'A long"));
+        let value_comment = read_long_string_cards(&cards);
+        assert_eq!(value_comment.value, Some(HeaderValue::from(value.as_str())));
+
+        let key = format!("HIERARCH {}", "KEY ".repeat(18));
+        match CardImage::from_long_string(key.trim_end(), &value, None) {
+            Err(error) => assert_eq!(error.to_string(), "Keyword is too long for a string value"),
+            Ok(_) => panic!("Should be an error!"),
+        }
+    }
+
+    #[test]
+    fn create_hdu_with_long_string() {
+        let path = temp_path("create_hdu_with_long_string.fits");
+        let value = "x".repeat(200);
+        let mut hdu = Hdu::new(&[1], vec![1.0f32]);
+        hdu.insert("FILENAME", value.as_str());
+        hdu.insert("OBJECT", "M31");
+        Fits::create(&path, hdu).unwrap();
+
+        let fits = Fits::open(&path).unwrap();
+        assert_eq!(fits[0].value("FILENAME"), Some(&HeaderValue::from(value)));
+        assert_eq!(fits[0].value("OBJECT"), Some(&HeaderValue::from("M31")));
+        drop(fits);
+
+        let value = format!("{}  ", "y".repeat(120));
+        let mut fits = Fits::open_mut(&path).unwrap();
+        fits[0].insert("HIERARCH ESO FILENAME", value.as_str());
+        fits.flush().unwrap();
+        let fits = Fits::open(&path).unwrap();
+        assert_eq!(
+            fits[0].value("HIERARCH ESO FILENAME"),
+            Some(&HeaderValue::from(value))
+        );
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn write_card_image_too_long() {
+        let card = CardImage::from_key_value("TOOLONGKEY", None, None);
+        assert!(card.is_err());
+    }
+
+    #[test]
+    fn create_primary_hdu() {
+        let path = temp_path("create_primary_hdu.fits");
+        let mut hdu = Hdu::new(&[3, 2], vec![1.5f32, 2.5, 3.5, 4.5, 5.5, 6.5]);
+        hdu.insert("OBJECT", "M31");
+        hdu.insert("EXPTIME", 13501.5);
+        {
+            let fits = Fits::create(&path, hdu).unwrap();
+            assert_eq!(fits.iter().count(), 1);
+            assert_eq!(fits[0].value("OBJECT"), Some(&HeaderValue::from("M31")));
+        }
+        let bytes = fs::read(&path).unwrap();
+        assert_eq!(bytes.len(), 2 * 2880);
+        assert_eq!(
+            &bytes[..80],
+            &CardImage::from("SIMPLE  =                    T").0[..]
+        );
+        assert_eq!(
+            &bytes[80..160],
+            &CardImage::from("BITPIX  =                  -32").0[..]
+        );
+
+        let fits = Fits::open(&path).unwrap();
+        assert_eq!(fits.iter().count(), 1);
+        let hdu = &fits[0];
+        assert_eq!(hdu.value("NAXIS2"), Some(&HeaderValue::IntegerNumber(2)));
+        assert_eq!(hdu.value("OBJECT"), Some(&HeaderValue::from("M31")));
+        assert_eq!(
+            hdu.value("EXPTIME"),
+            Some(&HeaderValue::RealFloatingNumber(13501.5))
+        );
+        match hdu.read_data() {
+            FitsData::FloatingPoint32(array) => {
+                assert_eq!(array.shape, vec![3, 2]);
+                assert_eq!(array.data, vec![1.5, 2.5, 3.5, 4.5, 5.5, 6.5]);
+            }
+            _ => panic!("Should be FloatingPoint32!"),
+        }
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn create_primary_hdu_with_blank() {
+        let path = temp_path("create_primary_hdu_with_blank.fits");
+        let data = vec![Some(1), None, Some(-3), Some(4)];
+        Fits::create(&path, Hdu::new(&[4], data.clone())).unwrap();
+        let fits = Fits::open(&path).unwrap();
+        match fits[0].read_data() {
+            FitsData::IntegersI32(array) => assert_eq!(array.data, data),
+            _ => panic!("Should be IntegersI32!"),
+        }
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn create_hdu_with_wrong_shape() {
+        let error = Hdu::try_new(&[3, 2], vec![1.5f32, 2.5]).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Data array does not match BITPIX and NAXISn keywords"
         );
-        assert_eq!(hdu.value("KY_IKYJ"), Some(&HeaderValue::IntegerNumber(51)));
+        assert!(Hdu::try_new(&[2], vec![1.5f32, 2.5]).is_ok());
+    }
+
+    #[test]
+    fn create_hdu_with_large_axis() {
+        let hdu = Hdu::new(&[3_000_000_000, 0], Vec::<f32>::new());
         assert_eq!(
-            hdu.value("KY_IKYE"),
-            Some(&HeaderValue::RealFloatingNumber(-1.3346E+01))
+            hdu.value("NAXIS1"),
+            Some(&HeaderValue::IntegerNumber(3_000_000_000))
         );
+        assert_eq!(hdu.value("NAXIS2"), Some(&HeaderValue::IntegerNumber(0)));
     }
 
     #[test]
-    fn compute_hdu_data_byte_length() {
-        let fits = Fits::open("test/testprog.fit").unwrap();
-        let mut iter = fits.into_iter();
-        let primary_hdu = iter.next().unwrap();
-        assert_eq!(primary_hdu.data_byte_length(), Some((32 / 8) * 10 * 2));
+    #[should_panic(expected = "Data array does not match BITPIX and NAXISn keywords")]
+    fn create_hdu_with_wrong_shape_panics() {
+        Hdu::new(&[3, 2], vec![1.5f32, 2.5]);
     }
 
     #[test]
-    fn iterate_over_hdu() {
+    fn copy_primary_hdu_to_new_file() {
+        let path = temp_path("copy_primary_hdu_to_new_file.fits");
         let fits = Fits::open("test/testprog.fit").unwrap();
-        let mut iter = fits.into_iter();
-        let primary_hdu = iter.next().unwrap();
-        assert_eq!(primary_hdu.header[0].0, "SIMPLE");
-        let hdu2 = iter.next().unwrap();
-        assert_eq!(hdu2.header[0].0, "XTENSION");
+        let primary_hdu = fits.into_iter().next().unwrap();
+        primary_hdu.read_data();
+        Fits::create(&path, primary_hdu).unwrap();
+        let original = Fits::open("test/testprog.fit").unwrap();
+        let copy = Fits::open(&path).unwrap();
         assert_eq!(
-            hdu2.value("XTENSION").unwrap(),
-            &HeaderValue::CharacterString(String::from("BINTABLE"))
+            copy[0].value("KY_IKYJ"),
+            Some(&HeaderValue::IntegerNumber(51))
         );
-        let hdu3 = iter.next().unwrap();
-        assert_eq!(hdu3.header[0].0, "XTENSION");
+        match (original[0].read_data(), copy[0].read_data()) {
+            (FitsData::IntegersI32(original), FitsData::IntegersI32(copy)) => {
+                assert_eq!(original.shape, copy.shape);
+                assert_eq!(original.data, copy.data);
+            }
+            _ => panic!("Should be IntegersI32!"),
+        }
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn push_image_extensions() {
+        let path = temp_path("push_image_extensions.fits");
+        let mut fits = Fits::create(&path, Hdu::empty()).unwrap();
+        let mut sci = Hdu::new(&[2, 2], vec![1.0f64, 2.0, 3.0, 4.0]);
+        sci.insert("EXTNAME", "SCI");
+        sci.insert("EXTVER", 1);
+        fits.push(sci).unwrap();
+        let mut dq = Hdu::new(&[2, 2], vec![0, 1, 0, 0]);
+        dq.insert("EXTNAME", "DQ");
+        dq.insert("EXTVER", 1);
+        fits.push(dq).unwrap();
+
+        assert_eq!(fits.iter().count(), 3);
+        assert_eq!(fits[1].value("XTENSION"), Some(&HeaderValue::from("IMAGE")));
+        assert_eq!(fits[1].value("SIMPLE"), None);
         assert_eq!(
-            hdu3.value("XTENSION").unwrap(),
-            &HeaderValue::CharacterString(String::from("IMAGE"))
+            fits["DQ"].value("EXTVER"),
+            Some(&HeaderValue::IntegerNumber(1))
         );
+        drop(fits);
+
+        let fits = Fits::open(&path).unwrap();
+        assert_eq!(fits.iter().count(), 3);
+        assert_eq!(fits[0].value("NAXIS"), Some(&HeaderValue::IntegerNumber(0)));
+        let sci = &fits["SCI"];
+        assert_eq!(sci.header[0].0, "XTENSION");
+        assert_eq!(sci.value("PCOUNT"), Some(&HeaderValue::IntegerNumber(0)));
+        assert_eq!(sci.value("GCOUNT"), Some(&HeaderValue::IntegerNumber(1)));
+        match sci.read_data() {
+            FitsData::FloatingPoint64(array) => assert_eq!(array.data, vec![1.0, 2.0, 3.0, 4.0]),
+            _ => panic!("Should be FloatingPoint64!"),
+        }
+        match fits["DQ"].read_data() {
+            FitsData::IntegersI32(array) => {
+                assert_eq!(array.data, vec![Some(0), Some(1), Some(0), Some(0)])
+            }
+            _ => panic!("Should be IntegersI32!"),
+        }
+        fs::remove_file(&path).unwrap();
     }
 
     #[test]
-    fn iterate_over_all_hdus() {
-        let fits = Fits::open("test/testprog.fit").unwrap();
-        assert_eq!(fits.into_iter().count(), 8);
+    fn push_image_extension_to_existing_file() {
+        let path = temp_path("push_image_extension_to_existing_file.fits");
+        fs::copy("test/testprog.fit", &path).unwrap();
+        let mut fits = Fits::open_mut(&path).unwrap();
+        let mut hdu = Hdu::new(&[3], vec![1.5f32, 2.5, 3.5]);
+        hdu.insert("EXTNAME", "NEW");
+        fits.push(hdu).unwrap();
+        assert_eq!(fits.iter().count(), 9);
+        assert_eq!(fits[8].value("EXTNAME"), Some(&HeaderValue::from("NEW")));
+        drop(fits);
+
+        let fits = Fits::open(&path).unwrap();
+        assert_eq!(fits.iter().count(), 9);
+        assert_eq!(
+            fits["Test-ASCII"].value("XTENSION"),
+            Some(&HeaderValue::from("TABLE"))
+        );
+        match fits["NEW"].read_data() {
+            FitsData::FloatingPoint32(array) => assert_eq!(array.data, vec![1.5, 2.5, 3.5]),
+            _ => panic!("Should be FloatingPoint32!"),
+        }
+        fs::remove_file(&path).unwrap();
     }
 
     #[test]
-    fn make_primary_hdu_array() {
-        let fits = Fits::open("test/testprog.fit").unwrap();
-        let mut iter = fits.into_iter();
-        let primary_hdu = iter.next().unwrap();
-        let data = primary_hdu.read_data();
-        match data {
-            &FitsData::IntegersI32(ref array) => {
-                assert_eq!(array.shape, vec![10, 2]);
+    fn push_tables_with_heap() {
+        let path = temp_path("push_tables_with_heap.fits");
+        let original = Fits::open("test/testprog.fit").unwrap();
+        let mut fits = Fits::create(&path, Hdu::new(&[2], vec![1, 2])).unwrap();
+        for hdu in Fits::open("test/testprog.fit")
+            .unwrap()
+            .into_iter()
+            .skip(3)
+            .take(3)
+        {
+            fits.push(hdu).unwrap();
+        }
+        drop(fits);
+
+        let fits = Fits::open(&path).unwrap();
+        assert_eq!(fits.iter().count(), 4);
+        let table = fits[1].read_ascii_table().unwrap();
+        let original_table = original[3].read_ascii_table().unwrap();
+        for column in 0..original_table.columns().len() {
+            assert_eq!(
+                format!("{:?}", table.column(column)),
+                format!("{:?}", original_table.column(column))
+            );
+        }
+        for i in 2..4 {
+            let table = fits[i].read_bintable().unwrap();
+            let original_table = original[i + 2].read_bintable().unwrap();
+            for column in 0..original_table.columns().len() {
                 assert_eq!(
-                    array.data,
-                    vec![
-                        None,
-                        Some(2),
-                        Some(3),
-                        None,
-                        Some(5),
-                        Some(6),
-                        Some(7),
-                        None,
-                        Some(9),
-                        Some(10),
-                        Some(11),
-                        None,
-                        Some(13),
-                        Some(14),
-                        Some(15),
-                        None,
-                        Some(17),
-                        Some(18),
-                        Some(19),
-                        None,
-                    ]
+                    format!("{:?}", table.column(column)),
+                    format!("{:?}", original_table.column(column))
                 );
             }
-            _ => panic!("Should be IntegersI32!"),
         }
+        fs::remove_file(&path).unwrap();
     }
 
     #[test]
-    fn read_second_hdu_array() {
-        // TODO TableHDU are not handled yet
+    fn push_in_memory_table_with_heap() {
+        let path = temp_path("push_in_memory_table_with_heap.fits");
+        let mut fits = Fits::create(&path, Hdu::new(&[2], vec![1, 2])).unwrap();
+        let mut hdu = Hdu::new(&[4, 1], vec![0u8; 4]);
+        hdu.insert("XTENSION", "BINTABLE");
+        hdu.set_value(String::from("PCOUNT"), HeaderValue::IntegerNumber(8));
+        match fits.push(hdu) {
+            Err(error) => assert!(error.to_string().contains("cannot be edited")),
+            Ok(_) => panic!("Should be an error!"),
+        }
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn read_bytes_as_text() {
         let fits = Fits::open("test/testprog.fit").unwrap();
-        let mut iter = fits.into_iter();
-        iter.next();
-        let table_hdu_1 = iter.next().unwrap();
-        let data = table_hdu_1.read_data();
-        match data {
-            &FitsData::Characters(ref array) => {
-                assert_eq!(array.shape, vec![61, 20]);
+        match fits["Test-ASCII"].read_data() {
+            FitsData::IntegersU8(array) => {
+                assert_eq!(array.shape, vec![76, 12]);
+                assert!(array.to_text().starts_with("first string"));
+            }
+            _ => panic!("Should be IntegersU8!"),
+        }
+    }
+
+    #[test]
+    fn create_bytes_with_blank() {
+        let path = temp_path("create_bytes_with_blank.fits");
+        let hdu = Hdu::new(&[4], vec![Some(b'a'), None, Some(0), Some(b'b')]);
+        Fits::create(&path, hdu).unwrap();
+
+        let fits = Fits::open(&path).unwrap();
+        assert_eq!(
+            fits[0].value("BLANK"),
+            Some(&HeaderValue::IntegerNumber(255))
+        );
+        match fits[0].read_data() {
+            FitsData::IntegersU8(array) => {
+                assert_eq!(array.data, vec![Some(b'a'), None, Some(0), Some(b'b')]);
+                assert_eq!(array.to_text(), "a\u{fffd}\u{0}b");
+            }
+            _ => panic!("Should be IntegersU8!"),
+        }
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn create_bytes_using_default_blank() {
+        let hdu = Hdu::new(&[4], vec![Some(255u8), None, Some(0), Some(2)]);
+        assert_eq!(hdu.value("BLANK"), Some(&HeaderValue::IntegerNumber(1)));
+        let mut data: Vec<_> = (0..=u8::MAX).map(Some).collect();
+        data.push(None);
+        assert!(matches!(
+            Hdu::try_new(&[257], data),
+            Err(FitsError::NoFreeBlank)
+        ));
+    }
+
+    #[test]
+    fn read_section_of_cube() {
+        let path = temp_path("read_section_of_cube.fits");
+        let hdu = Hdu::new(&[5, 4, 3], (0..60).map(|n| n as f32).collect());
+        Fits::create(&path, hdu).unwrap();
+
+        let fits = Fits::open(&path).unwrap();
+        match fits[0].read_section(&[1, 0, 1], &[5, 3, 3], &[2, 2, 1]) {
+            FitsData::FloatingPoint32(array) => {
+                assert_eq!(array.shape, vec![2, 2, 2]);
                 assert_eq!(
-                    &array.data[..30],
-                    &vec![
-                        '\u{0}', '\u{0}', '\u{0}', '\u{0}', '\u{0}', '\u{0}', '\u{0}', '\u{0}',
-                        '\u{0}', '\u{0}', '\u{0}', '\u{0}', '\u{0}', '\u{0}', '\u{0}', '\u{0}',
-                        '\u{0}', '\u{0}', '\u{0}', '\u{80}', '\u{0}', 'ÿ', 'ÿ', 'ÿ', 'ÿ',
-                        '\u{0}', '\u{0}', '\u{0}', '\u{0}', '\u{0}',
-                    ][..]
+                    array.data,
+                    vec![21.0, 23.0, 31.0, 33.0, 41.0, 43.0, 51.0, 53.0]
                 );
             }
-            _ => panic!("Should be Characters!"),
+            _ => panic!("Should be FloatingPoint32!"),
+        }
+        match fits[0].read_section(&[4, 3, 2], &[5, 4, 3], &[1, 1, 1]) {
+            FitsData::FloatingPoint32(array) => {
+                assert_eq!(array.shape, vec![1, 1, 1]);
+                assert_eq!(array.data, vec![59.0]);
+            }
+            _ => panic!("Should be FloatingPoint32!"),
         }
+        match fits[0].read_section(&[0, 0, 0], &[0, 4, 3], &[1, 1, 1]) {
+            FitsData::FloatingPoint32(array) => {
+                assert_eq!(array.shape, vec![0, 4, 3]);
+                assert!(array.data.is_empty());
+            }
+            _ => panic!("Should be FloatingPoint32!"),
+        }
+        fs::remove_file(&path).unwrap();
     }
 
     #[test]
-    fn iterate_over_hdu_no_consume() {
+    fn read_section_with_blank() {
         let fits = Fits::open("test/testprog.fit").unwrap();
-        let mut iter = fits.iter();
-        let primary_hdu = iter.next().unwrap();
-        assert_eq!(primary_hdu.header[0].0, "SIMPLE");
-        let hdu2 = iter.next().unwrap();
-        assert_eq!(hdu2.header[0].0, "XTENSION");
-        assert_eq!(
-            hdu2.value("XTENSION").unwrap(),
-            &HeaderValue::CharacterString(String::from("BINTABLE"))
-        );
-        let hdu3 = iter.next().unwrap();
-        assert_eq!(hdu3.header[0].0, "XTENSION");
-        assert_eq!(
-            hdu3.value("XTENSION").unwrap(),
-            &HeaderValue::CharacterString(String::from("IMAGE"))
-        );
+        let hdu = &fits[0];
+        let section = hdu.read_section(&[2, 0], &[10, 2], &[3, 1]);
+        match (section, hdu.read_data()) {
+            (FitsData::IntegersI32(section), FitsData::IntegersI32(array)) => {
+                assert_eq!(section.shape, vec![3, 2]);
+                let expected: Vec<_> = [2, 5, 8, 12, 15, 18]
+                    .iter()
+                    .map(|&i| array.data[i])
+                    .collect();
+                assert_eq!(section.data, expected);
+            }
+            _ => panic!("Should be IntegersI32!"),
+        }
     }
 
     #[test]
-    fn iterate_over_hdu_mut() {
+    fn read_section_of_cached_data() {
+        let hdu = Hdu::new(&[3, 2], vec![1.0f32, 2.0, 3.0, 4.0, 5.0, 6.0]);
+        match hdu.read_section(&[1, 0], &[3, 2], &[1, 1]) {
+            FitsData::FloatingPoint32(array) => {
+                assert_eq!(array.shape, vec![2, 2]);
+                assert_eq!(array.data, vec![2.0, 3.0, 5.0, 6.0]);
+            }
+            _ => panic!("Should be FloatingPoint32!"),
+        }
+
         let mut fits = Fits::open("test/testprog.fit").unwrap();
-        let mut iter = fits.iter_mut();
-        let primary_hdu = iter.next().unwrap();
-        assert_eq!(primary_hdu.header[0].0, "SIMPLE");
-        let hdu2 = iter.next().unwrap();
-        assert_eq!(hdu2.header[0].0, "XTENSION");
+        match fits[0].read_data_mut() {
+            FitsData::IntegersI32(array) => array.data[13] = Some(1234),
+            _ => panic!("Should be IntegersI32!"),
+        }
+        match fits[0].read_section(&[1, 1], &[6, 2], &[2, 1]) {
+            FitsData::IntegersI32(array) => {
+                assert_eq!(array.shape, vec![3, 1]);
+                assert_eq!(array.data, vec![None, Some(1234), None]);
+            }
+            _ => panic!("Should be IntegersI32!"),
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid section for axis 2")]
+    fn read_section_out_of_array() {
+        let fits = Fits::open("test/testprog.fit").unwrap();
+        fits[0].read_section(&[0, 0], &[10, 3], &[1, 1]);
+    }
+
+    #[test]
+    fn create_unsigned_16_bit_integers() {
+        let path = temp_path("create_unsigned_16_bit_integers.fits");
+        let hdu = Hdu::new(&[4], vec![Some(0u16), Some(32768), None, Some(1)]);
+        Fits::create(&path, hdu).unwrap();
+
+        let fits = Fits::open(&path).unwrap();
+        let hdu = &fits[0];
+        assert_eq!(hdu.value("BITPIX"), Some(&HeaderValue::IntegerNumber(16)));
+        assert_eq!(hdu.value("BZERO"), Some(&HeaderValue::IntegerNumber(32768)));
+        match hdu.read_data() {
+            FitsData::IntegersU16(array) => {
+                assert_eq!(array.data, vec![Some(0), Some(32768), None, Some(1)])
+            }
+            _ => panic!("Should be IntegersU16!"),
+        }
+        // Raw stored values are shifted by BZERO
+        let raw = fs::read(&path).unwrap();
+        assert_eq!(&raw[2880..2888], &[0x80, 0, 0, 0, 0x7f, 0xff, 0x80, 1]);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn create_integers_using_default_blank() {
+        let path = temp_path("create_integers_using_default_blank.fits");
+        let data = vec![Some(u16::MAX), None, Some(0), Some(2)];
+        let hdu = Hdu::new(&[4], data.clone());
+        // 65535 and 0 are stored as i16::MAX and i16::MIN
         assert_eq!(
-            hdu2.value("XTENSION").unwrap(),
-            &HeaderValue::CharacterString(String::from("BINTABLE"))
+            hdu.value("BLANK"),
+            Some(&HeaderValue::IntegerNumber(-32767))
         );
-        let hdu3 = iter.next().unwrap();
-        assert_eq!(hdu3.header[0].0, "XTENSION");
+        Fits::create(&path, hdu).unwrap();
+        let fits = Fits::open(&path).unwrap();
+        match fits[0].read_data() {
+            FitsData::IntegersU16(array) => assert_eq!(array.data, data),
+            _ => panic!("Should be IntegersU16!"),
+        }
+
+        let hdu = Hdu::new(&[3], vec![Some(i32::MIN), None, Some(i32::MIN + 1)]);
         assert_eq!(
-            hdu3.value("XTENSION").unwrap(),
-            &HeaderValue::CharacterString(String::from("IMAGE"))
+            hdu.value("BLANK"),
+            Some(&HeaderValue::IntegerNumber(i32::MIN as i64 + 2))
         );
+        let hdu = Hdu::new(&[2], vec![Some(u32::MAX), None]);
+        assert_eq!(
+            hdu.value("BLANK"),
+            Some(&HeaderValue::IntegerNumber(i32::MIN as i64))
+        );
+        let hdu = Hdu::new(&[2], vec![Some(1u16), Some(2)]);
+        assert_eq!(hdu.value("BLANK"), None);
+        fs::remove_file(&path).unwrap();
     }
 
     #[test]
-    fn iterate_over_hdu_mut_twice() {
-        let mut fits = Fits::open("test/testprog.fit").unwrap();
-        {
-            let mut iter = fits.iter_mut();
-            let primary_hdu = iter.next().unwrap();
-            assert_eq!(primary_hdu.header[0].0, "SIMPLE");
-            let hdu2 = iter.next().unwrap();
-            assert_eq!(hdu2.header[0].0, "XTENSION");
-            assert_eq!(
-                hdu2.value("XTENSION").unwrap(),
-                &HeaderValue::CharacterString(String::from("BINTABLE"))
-            );
-            let hdu3 = iter.next().unwrap();
-            assert_eq!(hdu3.header[0].0, "XTENSION");
-            assert_eq!(
-                hdu3.value("XTENSION").unwrap(),
-                &HeaderValue::CharacterString(String::from("IMAGE"))
-            );
+    fn create_integers_without_free_blank() {
+        let mut data: Vec<_> = (0..=u16::MAX).map(Some).collect();
+        data.push(None);
+        let error = Hdu::try_new(&[data.len()], data).unwrap_err();
+        assert_eq!(error.to_string(), "No value left for BLANK in integer data");
+    }
+
+    #[test]
+    fn create_unsigned_32_bit_integers() {
+        let path = temp_path("create_unsigned_32_bit_integers.fits");
+        let hdu = Hdu::new(&[3], vec![0u32, 1, u32::MAX]);
+        Fits::create(&path, hdu).unwrap();
+
+        let fits = Fits::open(&path).unwrap();
+        match fits[0].read_data() {
+            FitsData::IntegersU32(array) => {
+                assert_eq!(array.data, vec![Some(0), Some(1), Some(u32::MAX)])
+            }
+            _ => panic!("Should be IntegersU32!"),
         }
-        {
-            let mut iter = fits.iter_mut();
-            let primary_hdu = iter.next().unwrap();
-            assert_eq!(primary_hdu.header[0].0, "SIMPLE");
-            let hdu2 = iter.next().unwrap();
-            assert_eq!(hdu2.header[0].0, "XTENSION");
-            assert_eq!(
-                hdu2.value("XTENSION").unwrap(),
-                &HeaderValue::CharacterString(String::from("BINTABLE"))
-            );
-            let hdu3 = iter.next().unwrap();
-            assert_eq!(hdu3.header[0].0, "XTENSION");
-            assert_eq!(
-                hdu3.value("XTENSION").unwrap(),
-                &HeaderValue::CharacterString(String::from("IMAGE"))
-            );
+        match fits[0].read_physical_data() {
+            FitsData::IntegersU32(array) => assert_eq!(array.data[2], Some(u32::MAX)),
+            _ => panic!("Should be IntegersU32!"),
         }
+        fs::remove_file(&path).unwrap();
     }
 
     #[test]
-    fn index_over_fits() {
-        let fits = Fits::open("test/testprog.fit").unwrap();
-        let hdu2 = &fits[1];
-        assert_eq!(hdu2.header[0].0, "XTENSION");
+    fn create_64_bit_integers() {
+        let path = temp_path("create_64_bit_integers.fits");
+        let hdu = Hdu::new(&[2, 2], vec![Some(i64::MAX), None, Some(-1), Some(1 << 40)]);
+        Fits::create(&path, hdu).unwrap();
+
+        let fits = Fits::open(&path).unwrap();
+        let hdu = &fits[0];
+        assert_eq!(hdu.value("BITPIX"), Some(&HeaderValue::IntegerNumber(64)));
         assert_eq!(
-            hdu2.value("XTENSION").unwrap(),
-            &HeaderValue::CharacterString(String::from("BINTABLE"))
+            hdu.value("BLANK"),
+            Some(&HeaderValue::IntegerNumber(i64::MIN))
         );
+        assert_eq!(hdu.data_byte_length().ok(), Some(32));
+        match hdu.read_data() {
+            FitsData::IntegersI64(array) => {
+                assert_eq!(array.shape, vec![2, 2]);
+                assert_eq!(
+                    array.data,
+                    vec![Some(i64::MAX), None, Some(-1), Some(1 << 40)]
+                );
+            }
+            _ => panic!("Should be IntegersI64!"),
+        }
+        fs::remove_file(&path).unwrap();
     }
 
     #[test]
-    #[should_panic]
-    fn index_overflow_over_fits() {
-        let fits = Fits::open("test/testprog.fit").unwrap();
-        let _hdu2 = &fits[10];
+    fn create_unsigned_64_bit_integers() {
+        let path = temp_path("create_unsigned_64_bit_integers.fits");
+        let hdu = Hdu::new(&[3], vec![0u64, 1 << 63, u64::MAX]);
+        Fits::create(&path, hdu).unwrap();
+
+        let fits = Fits::open(&path).unwrap();
+        match fits[0].read_data() {
+            FitsData::IntegersU64(array) => {
+                assert_eq!(array.data, vec![Some(0), Some(1 << 63), Some(u64::MAX)])
+            }
+            _ => panic!("Should be IntegersU64!"),
+        }
+        let raw = fs::read(&path).unwrap();
+        assert_eq!(&raw[2880..2888], &[0x80, 0, 0, 0, 0, 0, 0, 0]);
+        fs::remove_file(&path).unwrap();
     }
 
     #[test]
-    fn index_with_string_over_fits() {
-        let fits = Fits::open("test/testprog.fit").unwrap();
-        let hdu2 = &fits["Test-ASCII"];
-        assert_eq!(hdu2.header[0].0, "XTENSION");
+    fn create_64_bit_integers_using_default_blank() {
+        let path = temp_path("create_64_bit_integers_using_default_blank.fits");
+        let data = vec![Some(i64::MIN), None, Some(i64::MAX)];
+        let hdu = Hdu::new(&[3], data.clone());
         assert_eq!(
-            hdu2.value("XTENSION").unwrap(),
-            &HeaderValue::CharacterString(String::from("TABLE"))
+            hdu.value("BLANK"),
+            Some(&HeaderValue::IntegerNumber(i64::MIN + 1))
+        );
+        Fits::create(&path, hdu).unwrap();
+        let fits = Fits::open(&path).unwrap();
+        match fits[0].read_data() {
+            FitsData::IntegersI64(array) => assert_eq!(array.data, data),
+            _ => panic!("Should be IntegersI64!"),
+        }
+
+        let hdu = Hdu::new(&[3], vec![Some(u64::MAX), None, Some(0)]);
+        assert_eq!(
+            hdu.value("BLANK"),
+            Some(&HeaderValue::IntegerNumber(i64::MIN + 1))
         );
+        fs::remove_file(&path).unwrap();
     }
 
     #[test]
-    #[should_panic]
-    fn index_with_string_not_found_over_fits() {
+    fn read_physical_data_without_scaling() {
         let fits = Fits::open("test/testprog.fit").unwrap();
-        let _hdu2 = &fits["FOOBAR"];
+        match (fits[0].read_physical_data(), fits[0].read_data()) {
+            (FitsData::IntegersI32(physical), FitsData::IntegersI32(raw)) => {
+                assert_eq!(physical.data, raw.data)
+            }
+            _ => panic!("Should be IntegersI32!"),
+        }
+    }
+
+    #[test]
+    fn read_physical_data_with_scaling() {
+        let path = temp_path("read_physical_data_with_scaling.fits");
+        let mut hdu = Hdu::new(&[3], vec![Some(-2), None, Some(4)]);
+        hdu.insert("BSCALE", 0.5);
+        hdu.insert("BZERO", 10);
+        Fits::create(&path, hdu).unwrap();
+
+        let fits = Fits::open(&path).unwrap();
+        match fits[0].read_data() {
+            FitsData::IntegersI32(array) => assert_eq!(array.data, vec![Some(-2), None, Some(4)]),
+            _ => panic!("Should be IntegersI32!"),
+        }
+        match fits[0].read_physical_data() {
+            FitsData::FloatingPoint64(array) => {
+                assert_eq!(array.data[0], 9.0);
+                assert!(array.data[1].is_nan());
+                assert_eq!(array.data[2], 12.0);
+            }
+            _ => panic!("Should be FloatingPoint64!"),
+        }
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn push_to_read_only_file() {
+        let mut fits = Fits::open("test/testprog.fit").unwrap();
+        assert!(fits.push(Hdu::new(&[1], vec![1])).is_err());
+        assert_eq!(fits.iter().count(), 8);
     }
 }
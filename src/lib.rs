@@ -1,6 +1,9 @@
 //! Library to parse FITS file written in pure rust.
 //!
-//! Uses only one dependency, [byteorder](../byteorder), to deal with endianness.
+//! Uses [byteorder](../byteorder) to deal with endianness, and
+//! [flate2](../flate2) and [bzip2](../bzip2) to read compressed files. The
+//! `mmap` feature, enabled by default, uses [memmap2](../memmap2) to map files
+//! in memory with `Fits::open_mmap`.
 //!
 //! Uses intelligent cache to parse big FITS files. Developed for use in
 //! multi-threaded environments.
@@ -18,6 +21,12 @@
 //!     println!("{:?}", hdu.read_data());
 //! }
 //!
+//! // Iterate over header cards
+//! for (key, value, comment) in fits[0].cards() {
+//!     println!("{} = {:?} / {:?}", key, value, comment);
+//! }
+//! println!("{:?}", fits[0].history());
+//!
 //! // Get HDU by ID
 //! let hdu_2 = &fits[2];
 //! // Get HDU by EXTNAME
@@ -30,14 +39,94 @@
 //!     }
 //!     _ => { /* ... */ }
 //! }
+//!
+//! // Read columns of a binary table
+//! let table = fits["EVENTS"].read_bintable().expect("Not a binary table");
+//! println!("{:?}", table.column_by_name("TIME"));
+//! ```
+//!
+//! # Write a FITS file
+//!
+//! ```rust,no_run
+//! extern crate fitrs;
+//! use fitrs::{Fits, Hdu};
+//!
+//! let data = (0..20).map(|n| n as f32).collect::<Vec<_>>();
+//! let mut primary_hdu = Hdu::new(&[5, 4], data);
+//! // Insert values in header
+//! primary_hdu.insert("OBJECT", "M31");
+//! primary_hdu.insert("EXPTIME", 13501.5);
+//! let mut fits = Fits::create("path/to/new/file.fits", primary_hdu).expect("Failed to create");
+//!
+//! // Append an IMAGE extension
+//! let mut hdu_mask = Hdu::new(&[5, 4], vec![0; 20]);
+//! hdu_mask.insert("EXTNAME", "MASK");
+//! fits.push(hdu_mask).expect("Failed to append extension");
+//! ```
+//!
+//! # Edit a file in place
+//!
+//! ```rust,no_run
+//! extern crate fitrs;
+//! use fitrs::{Fits, FitsData};
+//!
+//! let mut fits = Fits::open_mut("path/to/fits/file.fits").expect("Failed to open");
+//! fits[0].insert("OBJECT", "M31");
+//! fits[0].add_history("Fixed OBJECT").expect("Invalid history");
+//! if let FitsData::FloatingPoint32(array) = fits[0].read_data_mut() {
+//!     array.data[0] = 0.0;
+//! }
+//! fits.flush().expect("Failed to write changes");
+//! ```
+//!
+//! # Read a compressed file
+//!
+//! Files compressed with gzip, bzip2 or `compress` are decompressed
+//! transparently, in memory by default.
+//!
+//! ```rust,no_run
+//! extern crate fitrs;
+//! use fitrs::{Decompress, Fits};
+//!
+//! let fits = Fits::open("path/to/fits/file.fits.gz").expect("Failed to open");
+//! // Decompress to a temporary file instead
+//! let fits = Fits::open_with("path/to/fits/file.fits.Z", Decompress::TempFile)
+//!     .expect("Failed to open");
 //! ```
 //!
 //! A lot of possibly desirable functionalities are still missing.
 //! PR are welcome.
-//! Support for writing FITS file is planned.
 
 extern crate byteorder;
+extern crate bzip2;
+extern crate flate2;
+#[cfg(feature = "mmap")]
+extern crate memmap2;
+extern crate tempfile;
 
+mod compress;
+mod error;
+// Lints raised by the original code of the module, left as written
+#[allow(
+    mismatched_lifetime_syntaxes,
+    clippy::char_lit_as_u8,
+    clippy::empty_docs,
+    clippy::manual_find,
+    clippy::needless_borrowed_reference,
+    clippy::needless_lifetimes
+)]
 mod fits;
-pub use fits::{Fits, FitsData, FitsDataArray, Hdu, HeaderValue};
-pub use fits::{FitsIntoIter, FitsIter, FitsIterMut};
+#[cfg(feature = "mmap")]
+mod mmap;
+mod stream;
+mod table;
+pub use compress::Decompress;
+pub use error::FitsError;
+pub use fits::{Fits, FitsData, FitsDataArray, FitsDataType, Hdu, HeaderValue};
+pub use fits::{FitsIntoIter, FitsIter, FitsIterMut, FitsTryIter, FitsTryIterMut};
+pub use fits::{FromHeaderValue, HeaderIter, HierarchNode};
+#[cfg(feature = "mmap")]
+pub use mmap::{DataView, RawElement};
+pub use stream::{DataReader, FitsStream};
+pub use table::{AsciiColumn, AsciiColumnData, AsciiColumnType, AsciiTable};
+pub use table::{BinTable, Column, ColumnData, ColumnType};
@@ -0,0 +1,324 @@
+use std::fs::File;
+use std::io::{self, Cursor, ErrorKind, Read, Seek, SeekFrom, Write};
+use std::marker::PhantomData;
+use std::mem;
+use std::sync::Arc;
+
+use byteorder::{BigEndian, ByteOrder};
+use memmap2::Mmap;
+
+use fits::{Mapping, Storage};
+
+impl Mapping for Mmap {}
+
+#[derive(Debug)]
+struct MmapBytes(Arc<Mmap>);
+
+impl AsRef<[u8]> for MmapBytes {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+/// Read-only source of a [`Fits`](crate::Fits) opened with
+/// [`Fits::open_mmap`](crate::Fits::open_mmap).
+#[derive(Debug)]
+pub(crate) struct MmapStorage(Cursor<MmapBytes>);
+
+impl MmapStorage {
+    /// Map `file` in memory.
+    ///
+    /// # Safety
+    ///
+    /// The file must not be modified while it is mapped, as documented in
+    /// [`Fits::open_mmap`](crate::Fits::open_mmap).
+    pub(crate) unsafe fn new(file: &File) -> io::Result<Self> {
+        let map = Mmap::map(file)?;
+        Ok(MmapStorage(Cursor::new(MmapBytes(Arc::new(map)))))
+    }
+}
+
+impl Storage for MmapStorage {
+    fn mapping(&self) -> Option<Arc<dyn Mapping>> {
+        Some(self.0.get_ref().0.clone())
+    }
+}
+
+impl Read for MmapStorage {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.0.read(buf)
+    }
+}
+
+impl Seek for MmapStorage {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.0.seek(pos)
+    }
+}
+
+impl Write for MmapStorage {
+    fn write(&mut self, _buf: &[u8]) -> io::Result<usize> {
+        Err(io::Error::new(
+            ErrorKind::Unsupported,
+            "Memory-mapped FITS file cannot be written",
+        ))
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+mod sealed {
+    /// Supertrait of [`RawElement`](super::RawElement), so that it cannot be
+    /// implemented outside of this crate.
+    pub trait Sealed {}
+
+    impl Sealed for u8 {}
+    impl Sealed for i16 {}
+    impl Sealed for i32 {}
+    impl Sealed for i64 {}
+    impl Sealed for f32 {}
+    impl Sealed for f64 {}
+}
+
+/// Element types of a raw data array, as given by `BITPIX`.
+///
+/// This trait is sealed: it is only implemented for [`u8`], [`i16`],
+/// [`i32`], [`i64`], [`f32`] and [`f64`].
+pub trait RawElement: sealed::Sealed + Copy + 'static {
+    /// Value of `BITPIX` for this type.
+    const BITPIX: i64;
+    /// Decode an element from its big-endian bytes.
+    fn from_be_bytes(bytes: &[u8]) -> Self;
+}
+
+impl RawElement for u8 {
+    const BITPIX: i64 = 8;
+    fn from_be_bytes(bytes: &[u8]) -> Self {
+        bytes[0]
+    }
+}
+
+impl RawElement for i16 {
+    const BITPIX: i64 = 16;
+    fn from_be_bytes(bytes: &[u8]) -> Self {
+        BigEndian::read_i16(bytes)
+    }
+}
+
+impl RawElement for i32 {
+    const BITPIX: i64 = 32;
+    fn from_be_bytes(bytes: &[u8]) -> Self {
+        BigEndian::read_i32(bytes)
+    }
+}
+
+impl RawElement for i64 {
+    const BITPIX: i64 = 64;
+    fn from_be_bytes(bytes: &[u8]) -> Self {
+        BigEndian::read_i64(bytes)
+    }
+}
+
+impl RawElement for f32 {
+    const BITPIX: i64 = -32;
+    fn from_be_bytes(bytes: &[u8]) -> Self {
+        BigEndian::read_f32(bytes)
+    }
+}
+
+impl RawElement for f64 {
+    const BITPIX: i64 = -64;
+    fn from_be_bytes(bytes: &[u8]) -> Self {
+        BigEndian::read_f64(bytes)
+    }
+}
+
+/// Typed view of a data array mapped in memory. Obtained with
+/// [`Hdu::view`](crate::Hdu::view).
+///
+/// Elements are decoded from big-endian only when they are accessed, so
+/// only the pages of the file holding them are loaded.
+#[derive(Clone, Debug)]
+pub struct DataView<'a, T> {
+    bytes: &'a [u8],
+    shape: Vec<usize>,
+    element: PhantomData<T>,
+}
+
+impl<'a, T: RawElement> DataView<'a, T> {
+    pub(crate) fn new(bytes: &'a [u8], shape: Vec<usize>) -> Self {
+        let len = if shape.is_empty() {
+            0
+        } else {
+            shape.iter().product()
+        };
+        DataView {
+            bytes: &bytes[..len * mem::size_of::<T>()],
+            shape,
+            element: PhantomData,
+        }
+    }
+
+    /// Shape of the array, as given by `NAXISn`.
+    pub fn shape(&self) -> &[usize] {
+        &self.shape
+    }
+
+    /// Number of elements in the array.
+    pub fn len(&self) -> usize {
+        self.bytes.len() / mem::size_of::<T>()
+    }
+
+    /// Whether the array has no element.
+    pub fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+
+    /// Raw big-endian bytes of the array.
+    pub fn as_bytes(&self) -> &'a [u8] {
+        self.bytes
+    }
+
+    /// Get the element at `index` in the flattened array. Return [`None`]
+    /// if `index` is out of the array.
+    pub fn get(&self, index: usize) -> Option<T> {
+        let size = mem::size_of::<T>();
+        let start = index.checked_mul(size)?;
+        self.bytes
+            .get(start..start.checked_add(size)?)
+            .map(T::from_be_bytes)
+    }
+
+    /// Get the element at `coordinates`, given in the order of the axes
+    /// (first axis varying fastest). Return [`None`] if `coordinates` do not
+    /// have one element per axis or are out of the array.
+    pub fn get_pixel(&self, coordinates: &[usize]) -> Option<T> {
+        if coordinates.len() != self.shape.len() {
+            return None;
+        }
+        let mut index: usize = 0;
+        for (&coordinate, &n) in coordinates.iter().zip(&self.shape).rev() {
+            if coordinate >= n {
+                return None;
+            }
+            index = index.checked_mul(n)?.checked_add(coordinate)?;
+        }
+        self.get(index)
+    }
+
+    /// Iterate over all elements of the flattened array.
+    pub fn iter(&self) -> impl Iterator<Item = T> + 'a {
+        self.bytes
+            .chunks_exact(mem::size_of::<T>())
+            .map(T::from_be_bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use fits::{Fits, FitsData};
+    use std::fs;
+    use std::io::{self, ErrorKind};
+
+    fn open_mmap(path: &str) -> io::Result<Fits> {
+        // Test files are never modified
+        unsafe { Fits::open_mmap(path) }
+    }
+
+    #[test]
+    fn open_mmap_reads_same_as_open() {
+        let fits = Fits::open("test/testprog.fit").unwrap();
+        let mapped = open_mmap("test/testprog.fit").unwrap();
+        assert_eq!(mapped.iter().count(), 8);
+        for (hdu, mapped_hdu) in fits.iter().zip(mapped.iter()) {
+            assert_eq!(
+                format!("{:?}", hdu.read_bintable()),
+                format!("{:?}", mapped_hdu.read_bintable())
+            );
+            assert_eq!(
+                format!("{:?}", hdu.read_ascii_table()),
+                format!("{:?}", mapped_hdu.read_ascii_table())
+            );
+            assert_eq!(
+                format!("{:?}", hdu.try_read_data().ok()),
+                format!("{:?}", mapped_hdu.try_read_data().ok())
+            );
+            assert!(hdu.raw_data().is_none());
+            assert!(mapped_hdu.raw_data().is_some());
+        }
+    }
+
+    #[test]
+    fn raw_data_and_view() {
+        let bytes = fs::read("test/testprog.fit").unwrap();
+        let fits = open_mmap("test/testprog.fit").unwrap();
+        assert_eq!(fits[0].raw_data(), Some(&bytes[8640..8640 + 80]));
+        let view = fits[0].view::<i32>().unwrap();
+        assert_eq!(view.shape(), &[10, 2]);
+        assert_eq!(view.len(), 20);
+        assert_eq!(view.as_bytes(), &bytes[8640..8640 + 80]);
+        match fits[0].read_data() {
+            FitsData::IntegersI32(array) => {
+                let expected: Vec<i32> = array.data.iter().map(|n| n.unwrap_or(-99)).collect();
+                assert_eq!(view.iter().collect::<Vec<_>>(), expected);
+                assert_eq!(view.get(13), Some(expected[13]));
+                assert_eq!(view.get_pixel(&[3, 1]), Some(expected[13]));
+            }
+            _ => panic!("Expected IntegersI32"),
+        }
+        assert_eq!(view.get(20), None);
+        assert_eq!(view.get(usize::MAX), None);
+        assert_eq!(view.get(usize::MAX / 4 + 1), None);
+        assert_eq!(view.get_pixel(&[10, 0]), None);
+        assert_eq!(view.get_pixel(&[3]), None);
+    }
+
+    #[test]
+    fn view_of_float_image() {
+        let fits = open_mmap("test/testprog.fit").unwrap();
+        let view = fits[2].view::<f32>().unwrap();
+        assert_eq!(view.shape(), &[15, 25]);
+        match fits[2].read_data() {
+            FitsData::FloatingPoint32(array) => {
+                assert_eq!(view.get_pixel(&[3, 4]), Some(array.data[4 * 15 + 3]));
+                assert_eq!(view.iter().collect::<Vec<_>>(), array.data);
+            }
+            _ => panic!("Expected FloatingPoint32"),
+        }
+    }
+
+    #[test]
+    fn view_with_wrong_type() {
+        let fits = open_mmap("test/testprog.fit").unwrap();
+        let error = fits[0].try_view::<f32>().unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Data array does not match BITPIX and NAXISn keywords in HDU 0"
+        );
+    }
+
+    #[test]
+    fn read_section_from_mapping() {
+        let fits = Fits::open("test/testprog.fit").unwrap();
+        let mapped = open_mmap("test/testprog.fit").unwrap();
+        for &(hdu, start, end, step) in
+            &[(0, [1, 0], [9, 2], [3, 1]), (2, [2, 5], [15, 25], [2, 4])]
+        {
+            assert_eq!(
+                format!("{:?}", fits[hdu].read_section(&start, &end, &step)),
+                format!("{:?}", mapped[hdu].read_section(&start, &end, &step))
+            );
+        }
+    }
+
+    #[test]
+    fn mapped_file_is_read_only() {
+        let mut fits = open_mmap("test/testprog.fit").unwrap();
+        fits[0].insert("OBJECT", "M31");
+        assert!(fits.flush().is_err());
+        let error = open_mmap("test/testprog.fit.gz").unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidInput);
+    }
+}
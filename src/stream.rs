@@ -0,0 +1,217 @@
+use std::io::{self, Read};
+
+use error::FitsError;
+use fits::{padded_length, FitsData, Hdu};
+
+/// Forward-only reader of a FITS file, for sources that cannot seek such as
+/// standard input, sockets or decompressors.
+///
+/// Read the header of each HDU with [`FitsStream::next_hdu`], then read its
+/// data unit with [`FitsStream::read_data`] or [`FitsStream::data_reader`].
+/// The data left unread is skipped when reading the next HDU.
+///
+/// ```rust,no_run
+/// use fitrs::FitsStream;
+/// use std::io;
+///
+/// let stdin = io::stdin();
+/// let mut stream = FitsStream::new(stdin.lock());
+/// while let Some(mut hdu) = stream.next_hdu().expect("Failed to read header") {
+///     println!("{:?}", hdu.value("EXTNAME"));
+///     if hdu.value("XTENSION").is_none() {
+///         println!("{:?}", stream.read_data(&mut hdu).expect("Failed to read data"));
+///     }
+/// }
+/// ```
+pub struct FitsStream<R> {
+    reader: R,
+    /// Number of bytes read from `reader`.
+    position: u64,
+    /// Number of HDUs read.
+    count: usize,
+    /// Position of the data unit of the current HDU.
+    data_start: u64,
+    /// Length of the data unit of the current HDU, without padding.
+    data_length: u64,
+    /// Bytes of the data unit of the current HDU left to read, without
+    /// padding.
+    data_left: u64,
+    /// Padding bytes at the end of the data unit of the current HDU.
+    padding: u64,
+}
+
+impl<R: Read> FitsStream<R> {
+    /// Read FITS from `reader`, which is never seeked.
+    pub fn new(reader: R) -> Self {
+        FitsStream {
+            reader,
+            position: 0,
+            count: 0,
+            data_start: 0,
+            data_length: 0,
+            data_left: 0,
+            padding: 0,
+        }
+    }
+
+    /// Skip the rest of the current data unit, and read the header of the
+    /// next [`Hdu`].
+    ///
+    /// Return [`None`] at the end of the stream. The returned [`Hdu`] holds no
+    /// data until it is read with [`FitsStream::read_data`].
+    pub fn next_hdu(&mut self) -> Result<Option<Hdu>, FitsError> {
+        self.skip_data()?;
+        let hdu = match Hdu::read_header(&mut self.reader, self.count, self.position)? {
+            Some(hdu) => hdu,
+            None => return Ok(None),
+        };
+        self.position += hdu.header_byte_length();
+        self.count += 1;
+        self.data_start = self.position;
+        self.data_length = hdu.data_byte_length()?;
+        self.data_left = self.data_length;
+        self.padding = padded_length(self.data_length) - self.data_length;
+        Ok(Some(hdu))
+    }
+
+    /// Read the data array of `hdu`, the [`Hdu`] last returned by
+    /// [`FitsStream::next_hdu`], and cache it in `hdu`.
+    ///
+    /// Fail if part of the data unit was already read.
+    pub fn read_data<'h>(&mut self, hdu: &'h mut Hdu) -> Result<&'h FitsData, FitsError> {
+        let position = self.position;
+        if self.count == 0 || hdu.header_start() + hdu.header_byte_length() != self.data_start {
+            return Err(FitsError::Io {
+                offset: Some(position),
+                error: io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "Hdu is not the last one read from stream",
+                ),
+            });
+        }
+        if self.data_left != self.data_length {
+            return Err(FitsError::Io {
+                offset: Some(position),
+                error: io::Error::new(io::ErrorKind::InvalidInput, "Data unit was already read"),
+            });
+        }
+        hdu.read_data_from(&mut self.data_reader())
+    }
+
+    /// Reader over the bytes of the current data unit left to read, without
+    /// padding. Useful to read tables, or to copy data without decoding it.
+    pub fn data_reader(&mut self) -> DataReader<'_, R> {
+        DataReader { stream: self }
+    }
+
+    /// Skip the rest of the current data unit, including its padding.
+    pub fn skip_data(&mut self) -> Result<(), FitsError> {
+        let data_left = self.data_left;
+        let copied = io::copy(&mut self.data_reader(), &mut io::sink())?;
+        if copied != data_left {
+            return Err(FitsError::Io {
+                offset: Some(self.position),
+                error: io::Error::new(io::ErrorKind::UnexpectedEof, "Data unit is truncated"),
+            });
+        }
+        // The padding of the last data unit may be missing
+        let padding = io::copy(&mut (&mut self.reader).take(self.padding), &mut io::sink())
+            .map_err(|error| FitsError::Io {
+                offset: Some(self.position),
+                error,
+            })?;
+        self.position += padding;
+        self.padding = 0;
+        Ok(())
+    }
+}
+
+/// Reader over the bytes of the current data unit of a [`FitsStream`],
+/// created by [`FitsStream::data_reader`].
+pub struct DataReader<'a, R: 'a> {
+    stream: &'a mut FitsStream<R>,
+}
+
+impl<'a, R: Read> Read for DataReader<'a, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let max = (buf.len() as u64).min(self.stream.data_left) as usize;
+        let n = self.stream.reader.read(&mut buf[..max])?;
+        self.stream.data_left -= n as u64;
+        self.stream.position += n as u64;
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::FitsStream;
+    use error::FitsError;
+    use fits::Fits;
+    use std::fs;
+    use std::io::Read;
+
+    #[test]
+    fn stream_over_all_hdus() {
+        let bytes = fs::read("test/testprog.fit").unwrap();
+        let fits = Fits::open("test/testprog.fit").unwrap();
+        // A slice can only be read forward
+        let mut stream = FitsStream::new(&bytes[..]);
+        let mut count = 0;
+        while let Some(mut hdu) = stream.next_hdu().unwrap() {
+            let expected = &fits[count];
+            assert_eq!(hdu.value("EXTNAME"), expected.value("EXTNAME"));
+            assert_eq!(hdu.value("NAXIS2"), expected.value("NAXIS2"));
+            // Read images, skip tables
+            if let 0 | 2 | 6 = count {
+                assert_eq!(
+                    format!("{:?}", stream.read_data(&mut hdu).unwrap()),
+                    format!("{:?}", expected.read_data())
+                );
+                assert_eq!(
+                    format!("{:?}", hdu.read_data()),
+                    format!("{:?}", expected.read_data())
+                );
+            }
+            count += 1;
+        }
+        assert_eq!(count, 8);
+    }
+
+    #[test]
+    fn stream_raw_data() {
+        let bytes = fs::read("test/testprog.fit").unwrap();
+        let fits = Fits::open("test/testprog.fit").unwrap();
+        let mut stream = FitsStream::new(&bytes[..]);
+        stream.next_hdu().unwrap();
+        stream.next_hdu().unwrap();
+        let mut data = Vec::new();
+        stream.data_reader().read_to_end(&mut data).unwrap();
+        let start = (fits[1].header_start() + fits[1].header_byte_length()) as usize;
+        let len = fits[1].data_byte_length().unwrap() as usize;
+        assert_eq!(data, &bytes[start..start + len]);
+        let hdu = stream.next_hdu().unwrap().unwrap();
+        assert_eq!(hdu.value("XTENSION"), fits[2].value("XTENSION"));
+    }
+
+    #[test]
+    fn stream_read_data_errors() {
+        let bytes = fs::read("test/testprog.fit").unwrap();
+        let mut stream = FitsStream::new(&bytes[..]);
+        let mut primary = stream.next_hdu().unwrap().unwrap();
+        stream.data_reader().read_exact(&mut [0; 4]).unwrap();
+        assert!(stream.read_data(&mut primary).is_err());
+        stream.next_hdu().unwrap();
+        assert!(stream.read_data(&mut primary).is_err());
+    }
+
+    #[test]
+    fn stream_truncated_data() {
+        let bytes = fs::read("test/testprog.fit").unwrap();
+        let mut stream = FitsStream::new(&bytes[..8640 + 40]);
+        stream.next_hdu().unwrap();
+        match stream.next_hdu() {
+            Err(FitsError::Io { .. }) => (),
+            other => panic!("Should be an I/O error! Got {:?}", other.map(|_| ())),
+        }
+    }
+}
@@ -0,0 +1,1186 @@
+use std::convert::TryFrom;
+
+use byteorder::{BigEndian, ByteOrder};
+
+use error::FitsError;
+use fits::{FitsDataArray, Hdu, HeaderValue};
+
+/// A binary table read from a `BINTABLE` [`Hdu`].
+///
+/// As defined in [FITS standard 7.3](https://archive.stsci.edu/fits/fits_standard/node68.html#SECTION001230000000000000000).
+/// Obtained with [`Hdu::read_bintable`].
+#[derive(Debug)]
+pub struct BinTable {
+    /// Position of the [`Hdu`] in file, used in errors
+    hdu: Option<usize>,
+    columns: Vec<Column>,
+    row_length: usize,
+    row_count: usize,
+    /// Raw bytes of the main data table
+    data: Vec<u8>,
+    /// Raw bytes of the heap, holding variable length arrays
+    heap: Vec<u8>,
+}
+
+/// Description of a column inside a [`BinTable`].
+#[derive(Debug)]
+pub struct Column {
+    name: Option<String>,
+    repeat: usize,
+    data_type: ColumnType,
+    /// Set for variable length arrays
+    descriptor: Option<ArrayDescriptor>,
+    /// Position of the field in a row, in bytes
+    offset: usize,
+    null: Option<i64>,
+}
+
+/// Data type of the elements of a [`Column`], as defined by `TFORMn`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ColumnType {
+    /// `L`
+    Logical,
+    /// `X`
+    Bit,
+    /// `B`
+    Byte,
+    /// `I`
+    Integer16,
+    /// `J`
+    Integer32,
+    /// `K`
+    Integer64,
+    /// `A`
+    Character,
+    /// `E`
+    FloatingPoint32,
+    /// `D`
+    FloatingPoint64,
+    /// `C`
+    ComplexFloatingPoint32,
+    /// `M`
+    ComplexFloatingPoint64,
+}
+
+/// Array descriptor of a variable length array column.
+#[derive(Clone, Copy, Debug)]
+enum ArrayDescriptor {
+    /// `P`: a pair of 32-bit integers
+    P,
+    /// `Q`: a pair of 64-bit integers
+    Q,
+}
+
+/// Data of a whole [`Column`] inside a [`BinTable`].
+///
+/// Arrays have shape `[row_count]` if there is one element per field, or
+/// `[repeat, row_count]` if each field holds `repeat` elements.
+/// Integer values equal to `TNULLn` and undefined logical values are
+/// represented as [`None`].
+#[derive(Debug)]
+pub enum ColumnData {
+    Logical(FitsDataArray<Option<bool>>),
+    Bits(FitsDataArray<bool>),
+    Bytes(FitsDataArray<Option<u8>>),
+    IntegersI16(FitsDataArray<Option<i16>>),
+    IntegersI32(FitsDataArray<Option<i32>>),
+    IntegersI64(FitsDataArray<Option<i64>>),
+    /// One string per row, with trailing spaces removed.
+    Characters(Vec<String>),
+    FloatingPoint32(FitsDataArray<f32>),
+    FloatingPoint64(FitsDataArray<f64>),
+    ComplexFloatingPoint32(FitsDataArray<(f32, f32)>),
+    ComplexFloatingPoint64(FitsDataArray<(f64, f64)>),
+    /// One array of shape `[n]` per row, where `n` is the number of elements
+    /// in the row. Used for variable length array columns (`P` and `Q`
+    /// formats).
+    VariableLength(Vec<ColumnData>),
+}
+
+/// An ASCII table read from a `TABLE` [`Hdu`].
+///
+/// As defined in [FITS standard 7.2](https://archive.stsci.edu/fits/fits_standard/node57.html#SECTION001220000000000000000).
+/// Obtained with [`Hdu::read_ascii_table`].
+#[derive(Debug)]
+pub struct AsciiTable {
+    columns: Vec<AsciiColumn>,
+    row_length: usize,
+    row_count: usize,
+    /// Raw characters of the table
+    data: Vec<u8>,
+}
+
+/// Description of a column inside an [`AsciiTable`].
+#[derive(Debug)]
+pub struct AsciiColumn {
+    name: Option<String>,
+    data_type: AsciiColumnType,
+    /// Position of the field in a row, in characters, starting from 0
+    start: usize,
+    width: usize,
+    /// Number of digits after implied decimal point
+    decimals: u32,
+    null: Option<String>,
+}
+
+/// Data type of the fields of an [`AsciiColumn`], as defined by `TFORMn`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AsciiColumnType {
+    /// `Aw`
+    Character,
+    /// `Iw`
+    Integer,
+    /// `Fw.d`, `Ew.d` or `Dw.d`
+    FloatingPoint,
+}
+
+/// Data of a whole [`AsciiColumn`] inside an [`AsciiTable`].
+///
+/// Fields equal to `TNULLn`, or which cannot be parsed, are represented as
+/// [`None`].
+#[derive(Debug)]
+pub enum AsciiColumnData {
+    /// One string per row, with trailing spaces removed.
+    Characters(Vec<Option<String>>),
+    Integers(FitsDataArray<Option<i64>>),
+    FloatingPoint(FitsDataArray<Option<f64>>),
+}
+
+impl ColumnType {
+    fn new(c: char) -> Option<ColumnType> {
+        match c {
+            'L' => Some(ColumnType::Logical),
+            'X' => Some(ColumnType::Bit),
+            'B' => Some(ColumnType::Byte),
+            'I' => Some(ColumnType::Integer16),
+            'J' => Some(ColumnType::Integer32),
+            'K' => Some(ColumnType::Integer64),
+            'A' => Some(ColumnType::Character),
+            'E' => Some(ColumnType::FloatingPoint32),
+            'D' => Some(ColumnType::FloatingPoint64),
+            'C' => Some(ColumnType::ComplexFloatingPoint32),
+            'M' => Some(ColumnType::ComplexFloatingPoint64),
+            _ => None,
+        }
+    }
+
+    /// Size of one element in bytes. Bits are packed in bytes, so their size
+    /// is computed in [`Column::width`].
+    fn size(self) -> usize {
+        match self {
+            ColumnType::Logical | ColumnType::Bit | ColumnType::Byte => 1,
+            ColumnType::Character => 1,
+            ColumnType::Integer16 => 2,
+            ColumnType::Integer32 | ColumnType::FloatingPoint32 => 4,
+            ColumnType::Integer64 | ColumnType::FloatingPoint64 => 8,
+            ColumnType::ComplexFloatingPoint32 => 8,
+            ColumnType::ComplexFloatingPoint64 => 16,
+        }
+    }
+}
+
+impl Column {
+    /// Parse `TFORMn` value, which is of the form `rTa`.
+    ///
+    /// Array descriptors `P` and `Q` are followed by the type of the elements
+    /// of the variable length array.
+    fn new(name: Option<String>, tform: &str, offset: usize, null: Option<i64>) -> Option<Column> {
+        let tform = tform.trim();
+        let type_position = tform.find(|c: char| !c.is_ascii_digit())?;
+        let repeat = if type_position == 0 {
+            1
+        } else {
+            tform[..type_position].parse().ok()?
+        };
+        let mut chars = tform[type_position..].chars();
+        let descriptor = match tform[type_position..].chars().next()? {
+            'P' => Some(ArrayDescriptor::P),
+            'Q' => Some(ArrayDescriptor::Q),
+            _ => None,
+        };
+        if descriptor.is_some() {
+            chars.next();
+        }
+        let data_type = ColumnType::new(chars.next()?)?;
+        Some(Column {
+            name,
+            repeat,
+            data_type,
+            descriptor,
+            offset,
+            null,
+        })
+    }
+
+    /// Name of the column, as defined by `TTYPEn`.
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    /// Data type of the elements in the column.
+    pub fn data_type(&self) -> ColumnType {
+        self.data_type
+    }
+
+    /// Number of elements in each field of the column.
+    pub fn repeat(&self) -> usize {
+        self.repeat
+    }
+
+    /// Whether the column holds variable length arrays (`P` or `Q` format).
+    pub fn is_variable_length(&self) -> bool {
+        self.descriptor.is_some()
+    }
+
+    /// Width of a field in a row, in bytes, or [`None`] if it overflows.
+    fn width(&self) -> Option<usize> {
+        match (self.descriptor, self.data_type) {
+            (Some(ArrayDescriptor::P), _) => self.repeat.checked_mul(8),
+            (Some(ArrayDescriptor::Q), _) => self.repeat.checked_mul(16),
+            (None, ColumnType::Bit) => Some(self.repeat.div_ceil(8)),
+            (None, data_type) => self.repeat.checked_mul(data_type.size()),
+        }
+    }
+}
+
+impl BinTable {
+    /// Parse header of `hdu` and read its whole data table.
+    ///
+    /// Return [`None`] if `hdu` is not a `BINTABLE` extension.
+    pub(crate) fn new(hdu: &Hdu) -> Result<Option<BinTable>, FitsError> {
+        match hdu.value("XTENSION") {
+            Some(HeaderValue::CharacterString(xtension)) if xtension == "BINTABLE" => (),
+            _ => return Ok(None),
+        }
+        let (row_length, row_count) = table_size(hdu)?;
+        let fields = hdu.integer_keyword("TFIELDS")?;
+        let mut columns = Vec::new();
+        let mut offset = 0;
+        for i in 1..(fields + 1) {
+            let name = match hdu.value(&format!("TTYPE{}", i)) {
+                Some(HeaderValue::CharacterString(name)) => Some(name.clone()),
+                _ => None,
+            };
+            let tform_key = format!("TFORM{}", i);
+            let tform = match hdu.value(&tform_key) {
+                Some(HeaderValue::CharacterString(tform)) => tform,
+                Some(_) => return Err(hdu.invalid_keyword(&tform_key)),
+                None => return Err(hdu.missing_keyword(&tform_key)),
+            };
+            let null = hdu.value_as_integer_number(&format!("TNULL{}", i));
+            let column = Column::new(name, tform, offset, null)
+                .ok_or_else(|| hdu.invalid_keyword(&tform_key))?;
+            offset = column
+                .width()
+                .and_then(|width| offset.checked_add(width))
+                .ok_or_else(|| hdu.invalid_keyword(&tform_key))?;
+            columns.push(column);
+        }
+        if offset > row_length {
+            return Err(hdu.invalid_keyword("NAXIS1"));
+        }
+        // The heap starts at THEAP bytes from the start of the data unit and
+        // ends after the PCOUNT bytes following the main data table.
+        let main_length = row_length
+            .checked_mul(row_count)
+            .ok_or_else(|| hdu.invalid_keyword("NAXIS2"))?;
+        let pcount = hdu.optional_size_keyword("PCOUNT", 0)?;
+        let theap = hdu.optional_size_keyword("THEAP", main_length)?;
+        let data_length = main_length
+            .checked_add(pcount)
+            .ok_or_else(|| hdu.invalid_keyword("PCOUNT"))?;
+        let mut data = hdu.read_raw_data(data_length)?;
+        let heap = data.split_off(theap.min(data.len()));
+        data.truncate(main_length);
+        Ok(Some(BinTable {
+            hdu: hdu.hdu_index(),
+            columns,
+            row_length,
+            row_count,
+            data,
+            heap,
+        }))
+    }
+
+    /// Number of rows in the table.
+    pub fn row_count(&self) -> usize {
+        self.row_count
+    }
+
+    /// Description of all the columns in the table.
+    pub fn columns(&self) -> &[Column] {
+        &self.columns
+    }
+
+    /// Get data of column by index. Use `0` for the first column.
+    ///
+    /// Panic if a variable length array descriptor of the column is invalid.
+    /// See [`BinTable::try_column`].
+    pub fn column(&self, index: usize) -> Option<ColumnData> {
+        self.try_column(index).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Get data of column by index, or an error if the column cannot be
+    /// decoded. See [`BinTable::column`].
+    pub fn try_column(&self, index: usize) -> Result<Option<ColumnData>, FitsError> {
+        match self.columns.get(index) {
+            Some(column) => self.read_column(index, column).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Get data of column by name, as defined by `TTYPEn`.
+    ///
+    /// Panic if a variable length array descriptor of the column is invalid.
+    /// See [`BinTable::try_column_by_name`].
+    pub fn column_by_name(&self, name: &str) -> Option<ColumnData> {
+        self.try_column_by_name(name)
+            .unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Get data of column by name, or an error if the column cannot be
+    /// decoded. See [`BinTable::column_by_name`].
+    pub fn try_column_by_name(&self, name: &str) -> Result<Option<ColumnData>, FitsError> {
+        match self
+            .columns
+            .iter()
+            .position(|column| column.name() == Some(name))
+        {
+            Some(index) => self.try_column(index),
+            None => Ok(None),
+        }
+    }
+
+    fn read_column(&self, index: usize, column: &Column) -> Result<ColumnData, FitsError> {
+        let fields = self.fields(column);
+        let descriptor = match column.descriptor {
+            None => {
+                return Ok(decode(
+                    column.data_type,
+                    column.null,
+                    column.repeat,
+                    &self.shape(column),
+                    &fields,
+                ))
+            }
+            Some(descriptor) => descriptor,
+        };
+        let mut arrays = Vec::with_capacity(fields.len());
+        for (row, field) in fields.iter().enumerate() {
+            let invalid = || FitsError::InvalidDescriptor {
+                hdu: self.hdu,
+                column: index,
+                row,
+            };
+            let (count, offset) = descriptor.read(field).ok_or_else(invalid)?;
+            let len = match column.data_type {
+                ColumnType::Bit => Some(count.div_ceil(8)),
+                data_type => count.checked_mul(data_type.size()),
+            };
+            let array = len
+                .and_then(|len| offset.checked_add(len))
+                .and_then(|end| self.heap.get(offset..end))
+                .ok_or_else(invalid)?;
+            arrays.push(decode(
+                column.data_type,
+                column.null,
+                count,
+                &[count],
+                &[array],
+            ));
+        }
+        Ok(ColumnData::VariableLength(arrays))
+    }
+
+    /// Bytes of the fields of `column`, one row at a time.
+    fn fields(&self, column: &Column) -> Vec<&[u8]> {
+        let start = column.offset;
+        // Fields were checked to fit in rows when reading the header
+        let end = start + column.width().unwrap();
+        rows(&self.data, self.row_length, self.row_count)
+            .map(|row| &row[start..end])
+            .collect()
+    }
+
+    fn shape(&self, column: &Column) -> Vec<usize> {
+        if column.repeat == 1 {
+            vec![self.row_count]
+        } else {
+            vec![column.repeat, self.row_count]
+        }
+    }
+}
+
+impl AsciiColumn {
+    /// Parse `TFORMn` value, which is of the form `Aw`, `Iw`, `Fw.d`, `Ew.d`
+    /// or `Dw.d`.
+    fn new(
+        name: Option<String>,
+        tform: &str,
+        tbcol: usize,
+        null: Option<String>,
+    ) -> Option<AsciiColumn> {
+        let tform = tform.trim();
+        let data_type = match tform.chars().next()? {
+            'A' => AsciiColumnType::Character,
+            'I' => AsciiColumnType::Integer,
+            'F' | 'E' | 'D' => AsciiColumnType::FloatingPoint,
+            _ => return None,
+        };
+        let mut width_decimals = tform[1..].splitn(2, '.');
+        let width = width_decimals.next()?.parse().ok()?;
+        let decimals = match width_decimals.next() {
+            Some(decimals) => decimals.parse().ok()?,
+            None => 0,
+        };
+        Some(AsciiColumn {
+            name,
+            data_type,
+            start: tbcol.checked_sub(1)?,
+            width,
+            decimals,
+            null,
+        })
+    }
+
+    /// Name of the column, as defined by `TTYPEn`.
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    /// Data type of the fields in the column.
+    pub fn data_type(&self) -> AsciiColumnType {
+        self.data_type
+    }
+
+    /// Width of a field in characters.
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    /// Parse a floating point field, written in Fortran format.
+    ///
+    /// The exponent may be introduced by `D`. If the field has no decimal
+    /// point, the last `decimals` digits are taken as the decimal part.
+    fn parse_floating_point(&self, field: &str) -> Option<f64> {
+        let field = field.replace('D', "E");
+        if field.is_empty() || field.contains('.') || self.decimals == 0 {
+            return field.parse().ok();
+        }
+        let (mantissa, exponent) = field.split_at(field.find('E').unwrap_or(field.len()));
+        let (sign, digits) = match mantissa.chars().next() {
+            Some('+') | Some('-') => mantissa.split_at(1),
+            _ => ("", mantissa),
+        };
+        let decimals = self.decimals as usize;
+        let digits = format!("{:0>width$}", digits, width = decimals);
+        let (integer, fraction) = digits.split_at(digits.len() - decimals);
+        format!("{}{}.{}{}", sign, integer, fraction, exponent)
+            .parse()
+            .ok()
+    }
+}
+
+impl AsciiTable {
+    /// Parse header of `hdu` and read its whole data table.
+    ///
+    /// Return [`None`] if `hdu` is not a `TABLE` extension.
+    pub(crate) fn new(hdu: &Hdu) -> Result<Option<AsciiTable>, FitsError> {
+        match hdu.value("XTENSION") {
+            Some(HeaderValue::CharacterString(xtension)) if xtension == "TABLE" => (),
+            _ => return Ok(None),
+        }
+        let (row_length, row_count) = table_size(hdu)?;
+        let fields = hdu.integer_keyword("TFIELDS")?;
+        let mut columns = Vec::new();
+        for i in 1..(fields + 1) {
+            let name = match hdu.value(&format!("TTYPE{}", i)) {
+                Some(HeaderValue::CharacterString(name)) => Some(name.clone()),
+                _ => None,
+            };
+            let tform_key = format!("TFORM{}", i);
+            let tform = match hdu.value(&tform_key) {
+                Some(HeaderValue::CharacterString(tform)) => tform,
+                Some(_) => return Err(hdu.invalid_keyword(&tform_key)),
+                None => return Err(hdu.missing_keyword(&tform_key)),
+            };
+            let tbcol = hdu.size_keyword(&format!("TBCOL{}", i))?;
+            let null = match hdu.value(&format!("TNULL{}", i)) {
+                Some(HeaderValue::CharacterString(null)) => Some(null.trim().to_owned()),
+                _ => None,
+            };
+            let column = AsciiColumn::new(name, tform, tbcol, null)
+                .ok_or_else(|| hdu.invalid_keyword(&tform_key))?;
+            match column.start.checked_add(column.width) {
+                Some(end) if end <= row_length => (),
+                _ => return Err(hdu.invalid_keyword(&format!("TBCOL{}", i))),
+            }
+            columns.push(column);
+        }
+        let data_length = row_length
+            .checked_mul(row_count)
+            .ok_or_else(|| hdu.invalid_keyword("NAXIS2"))?;
+        let data = hdu.read_raw_data(data_length)?;
+        Ok(Some(AsciiTable {
+            columns,
+            row_length,
+            row_count,
+            data,
+        }))
+    }
+
+    /// Number of rows in the table.
+    pub fn row_count(&self) -> usize {
+        self.row_count
+    }
+
+    /// Description of all the columns in the table.
+    pub fn columns(&self) -> &[AsciiColumn] {
+        &self.columns
+    }
+
+    /// Get data of column by index. Use `0` for the first column.
+    pub fn column(&self, index: usize) -> Option<AsciiColumnData> {
+        self.columns
+            .get(index)
+            .map(|column| self.read_column(column))
+    }
+
+    /// Get data of column by name, as defined by `TTYPEn`.
+    pub fn column_by_name(&self, name: &str) -> Option<AsciiColumnData> {
+        self.columns
+            .iter()
+            .find(|column| column.name() == Some(name))
+            .map(|column| self.read_column(column))
+    }
+
+    fn read_column(&self, column: &AsciiColumn) -> AsciiColumnData {
+        let start = column.start;
+        // Fields were checked to fit in rows when reading the header
+        let end = start.checked_add(column.width).unwrap();
+        let fields = rows(&self.data, self.row_length, self.row_count)
+            .map(|row| String::from_utf8_lossy(&row[start..end]))
+            .map(|field| {
+                if column.null.as_deref() == Some(field.trim()) {
+                    None
+                } else {
+                    Some(field)
+                }
+            });
+        let shape = [self.row_count];
+        match column.data_type {
+            AsciiColumnType::Character => AsciiColumnData::Characters(
+                fields
+                    .map(|field| field.map(|field| field.trim_end_matches(' ').to_owned()))
+                    .collect(),
+            ),
+            AsciiColumnType::Integer => AsciiColumnData::Integers(FitsDataArray::new(
+                &shape,
+                fields
+                    .map(|field| field.and_then(|field| field.trim().parse().ok()))
+                    .collect(),
+            )),
+            AsciiColumnType::FloatingPoint => AsciiColumnData::FloatingPoint(FitsDataArray::new(
+                &shape,
+                fields
+                    .map(|field| field.and_then(|field| column.parse_floating_point(field.trim())))
+                    .collect(),
+            )),
+        }
+    }
+}
+
+/// Read `NAXIS1` and `NAXIS2`, the length and number of rows of a table.
+///
+/// Rows must not be empty, unless the table has no row.
+fn table_size(hdu: &Hdu) -> Result<(usize, usize), FitsError> {
+    let row_length = hdu.size_keyword("NAXIS1")?;
+    let row_count = hdu.size_keyword("NAXIS2")?;
+    if row_length == 0 && row_count > 0 {
+        return Err(hdu.invalid_keyword("NAXIS1"));
+    }
+    Ok((row_length, row_count))
+}
+
+/// Split `data` in `row_count` rows of `row_length` bytes.
+fn rows(data: &[u8], row_length: usize, row_count: usize) -> impl Iterator<Item = &[u8]> {
+    // Rows are only empty in a table without row
+    data.chunks(row_length.max(1)).take(row_count)
+}
+
+impl ArrayDescriptor {
+    /// Read number of elements and byte offset in heap of a variable length
+    /// array. Return [`None`] if any of them is negative or does not fit in
+    /// memory.
+    fn read(self, field: &[u8]) -> Option<(usize, usize)> {
+        let (count, offset) = match self {
+            ArrayDescriptor::P => (
+                i64::from(BigEndian::read_i32(field)),
+                i64::from(BigEndian::read_i32(&field[4..])),
+            ),
+            ArrayDescriptor::Q => (BigEndian::read_i64(field), BigEndian::read_i64(&field[8..])),
+        };
+        Some((usize::try_from(count).ok()?, usize::try_from(offset).ok()?))
+    }
+}
+
+/// Decode `fields` holding `repeat` elements of type `data_type` each.
+fn decode(
+    data_type: ColumnType,
+    null: Option<i64>,
+    repeat: usize,
+    shape: &[usize],
+    fields: &[&[u8]],
+) -> ColumnData {
+    match data_type {
+        ColumnType::Logical => {
+            ColumnData::Logical(collect(data_type, shape, fields, |b| match b[0] {
+                b'T' => Some(true),
+                b'F' => Some(false),
+                _ => None,
+            }))
+        }
+        ColumnType::Bit => {
+            let mut data = Vec::with_capacity(repeat * fields.len());
+            for field in fields {
+                for i in 0..repeat {
+                    data.push(field[i / 8] & (0x80 >> (i % 8)) != 0);
+                }
+            }
+            ColumnData::Bits(FitsDataArray::new(shape, data))
+        }
+        ColumnType::Byte => ColumnData::Bytes(collect(data_type, shape, fields, |b| {
+            not_null(b[0], null, i64::from(b[0]))
+        })),
+        ColumnType::Integer16 => ColumnData::IntegersI16(collect(data_type, shape, fields, |b| {
+            let n = BigEndian::read_i16(b);
+            not_null(n, null, i64::from(n))
+        })),
+        ColumnType::Integer32 => ColumnData::IntegersI32(collect(data_type, shape, fields, |b| {
+            let n = BigEndian::read_i32(b);
+            not_null(n, null, i64::from(n))
+        })),
+        ColumnType::Integer64 => ColumnData::IntegersI64(collect(data_type, shape, fields, |b| {
+            let n = BigEndian::read_i64(b);
+            not_null(n, null, n)
+        })),
+        ColumnType::Character => ColumnData::Characters(
+            fields
+                .iter()
+                .map(|field| {
+                    let end = field.iter().position(|c| *c == 0).unwrap_or(field.len());
+                    String::from_utf8_lossy(&field[..end])
+                        .trim_end_matches(' ')
+                        .to_owned()
+                })
+                .collect(),
+        ),
+        ColumnType::FloatingPoint32 => {
+            ColumnData::FloatingPoint32(collect(data_type, shape, fields, BigEndian::read_f32))
+        }
+        ColumnType::FloatingPoint64 => {
+            ColumnData::FloatingPoint64(collect(data_type, shape, fields, BigEndian::read_f64))
+        }
+        ColumnType::ComplexFloatingPoint32 => {
+            ColumnData::ComplexFloatingPoint32(collect(data_type, shape, fields, |b| {
+                (BigEndian::read_f32(b), BigEndian::read_f32(&b[4..]))
+            }))
+        }
+        ColumnType::ComplexFloatingPoint64 => {
+            ColumnData::ComplexFloatingPoint64(collect(data_type, shape, fields, |b| {
+                (BigEndian::read_f64(b), BigEndian::read_f64(&b[8..]))
+            }))
+        }
+    }
+}
+
+/// Convert each element of type `data_type` in `fields` with `convert`,
+/// which is given a slice starting at the element.
+fn collect<T, F>(
+    data_type: ColumnType,
+    shape: &[usize],
+    fields: &[&[u8]],
+    convert: F,
+) -> FitsDataArray<T>
+where
+    F: Fn(&[u8]) -> T,
+{
+    let size = data_type.size();
+    let mut data = Vec::with_capacity(shape.iter().product());
+    for field in fields {
+        for element in field.chunks(size) {
+            data.push(convert(element));
+        }
+    }
+    FitsDataArray::new(shape, data)
+}
+
+fn not_null<T>(value: T, null: Option<i64>, as_i64: i64) -> Option<T> {
+    if null == Some(as_i64) {
+        None
+    } else {
+        Some(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ArrayDescriptor, BinTable, Column, ColumnData, ColumnType};
+    use super::{AsciiColumn, AsciiColumnData, AsciiColumnType};
+    use error::FitsError;
+    use fits::tests::temp_path;
+    use fits::{Fits, Hdu};
+    use std::fs;
+
+    #[test]
+    fn read_bintable_columns() {
+        let fits = Fits::open("test/testprog.fit").unwrap();
+        let table = fits[4].read_bintable().unwrap();
+        assert_eq!(table.row_count(), 22);
+        let columns = table.columns();
+        assert_eq!(columns.len(), 10);
+        assert_eq!(columns[0].name(), Some("Avalue"));
+        assert_eq!(columns[0].data_type(), ColumnType::Character);
+        assert_eq!(columns[0].repeat(), 15);
+        assert_eq!(columns[7].name(), Some("INSERT_COL"));
+        assert_eq!(columns[9].data_type(), ColumnType::ComplexFloatingPoint64);
+    }
+
+    #[test]
+    fn read_bintable_not_a_bintable() {
+        let fits = Fits::open("test/testprog.fit").unwrap();
+        assert!(fits[0].read_bintable().is_none());
+        assert!(fits["Test-ASCII"].read_bintable().is_none());
+    }
+
+    #[test]
+    fn try_read_bintable_missing_tform() {
+        let path = temp_path("try_read_bintable_missing_tform.fits");
+        let mut fits = Fits::create(&path, Hdu::empty()).unwrap();
+        let mut hdu = Hdu::new(&[4, 2], vec![0u8; 8]);
+        hdu.insert("XTENSION", "BINTABLE");
+        hdu.insert("TFIELDS", 1);
+        fits.push(hdu).unwrap();
+        drop(fits);
+
+        let fits = Fits::open(&path).unwrap();
+        assert!(fits[0].try_read_bintable().unwrap().is_none());
+        match fits[1].try_read_bintable() {
+            Err(FitsError::MissingKeyword { hdu, keyword }) => {
+                assert_eq!(hdu, Some(1));
+                assert_eq!(keyword, "TFORM1");
+            }
+            _ => panic!("Should be MissingKeyword!"),
+        }
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn try_read_bintable_corrupt_tform() {
+        let path = temp_path("try_read_bintable_corrupt_tform.fits");
+        let mut fits = Fits::create(&path, Hdu::empty()).unwrap();
+        for tforms in &[
+            ["4611686018427387904J", "1B"],
+            ["1B", "18446744073709551615B"],
+            ["2J", "1B"],
+        ] {
+            let mut hdu = Hdu::new(&[4, 2], vec![0u8; 8]);
+            hdu.insert("XTENSION", "BINTABLE");
+            hdu.insert("TFIELDS", 2);
+            hdu.insert("TFORM1", tforms[0]);
+            hdu.insert("TFORM2", tforms[1]);
+            fits.push(hdu).unwrap();
+        }
+        drop(fits);
+
+        let fits = Fits::open(&path).unwrap();
+        for &(i, key) in &[(1, "TFORM1"), (2, "TFORM2"), (3, "NAXIS1")] {
+            match fits[i].try_read_bintable() {
+                Err(FitsError::InvalidKeyword { hdu, keyword }) => {
+                    assert_eq!(hdu, Some(i));
+                    assert_eq!(keyword, key);
+                }
+                other => panic!("Should be InvalidKeyword! Got {:?}", other),
+            }
+        }
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn try_read_ascii_table_malformed_tbcol() {
+        let path = temp_path("try_read_ascii_table_malformed_tbcol.fits");
+        let mut fits = Fits::create(&path, Hdu::empty()).unwrap();
+        for &(tform, tbcol) in &[("A9223372036854775810", i64::MAX), ("A2", 4), ("A2", 3)] {
+            let mut hdu = Hdu::new(&[4, 1], b"  12".to_vec());
+            hdu.insert("XTENSION", "TABLE");
+            hdu.insert("TFIELDS", 1);
+            hdu.insert("TFORM1", tform);
+            hdu.insert("TBCOL1", tbcol);
+            fits.push(hdu).unwrap();
+        }
+        drop(fits);
+
+        let fits = Fits::open(&path).unwrap();
+        for i in 1..3 {
+            let error = fits[i].try_read_ascii_table().unwrap_err();
+            assert_eq!(
+                error.to_string(),
+                format!("Invalid value for keyword TBCOL1 in HDU {}", i)
+            );
+        }
+        let table = fits[3].try_read_ascii_table().unwrap().unwrap();
+        match table.column(0) {
+            Some(AsciiColumnData::Characters(fields)) => {
+                assert_eq!(fields, vec![Some(String::from("12"))])
+            }
+            _ => panic!("Should be Characters!"),
+        }
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn try_read_tables_with_empty_rows() {
+        let path = temp_path("try_read_tables_with_empty_rows.fits");
+        let mut fits = Fits::create(&path, Hdu::empty()).unwrap();
+        for &(xtension, tform, row_count) in &[
+            ("BINTABLE", "0J", 3),
+            ("BINTABLE", "0J", 0),
+            ("TABLE", "A0", 2),
+        ] {
+            let mut hdu = Hdu::new(&[0, row_count], Vec::<u8>::new());
+            hdu.insert("XTENSION", xtension);
+            hdu.insert("TFIELDS", 1);
+            hdu.insert("TFORM1", tform);
+            hdu.insert("TBCOL1", 1);
+            fits.push(hdu).unwrap();
+        }
+        drop(fits);
+
+        let fits = Fits::open(&path).unwrap();
+        let error = fits[1].try_read_bintable().unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Invalid value for keyword NAXIS1 in HDU 1"
+        );
+        let table = fits[2].try_read_bintable().unwrap().unwrap();
+        match table.column(0) {
+            Some(ColumnData::IntegersI32(array)) => assert!(array.data.is_empty()),
+            _ => panic!("Should be IntegersI32!"),
+        }
+        let error = fits[3].try_read_ascii_table().unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Invalid value for keyword NAXIS1 in HDU 3"
+        );
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn read_bintable_character_column() {
+        let fits = Fits::open("test/testprog.fit").unwrap();
+        let table = fits[4].read_bintable().unwrap();
+        match table.column_by_name("Avalue") {
+            Some(ColumnData::Characters(strings)) => {
+                assert_eq!(strings.len(), 22);
+                assert_eq!(strings[0], "first string");
+                assert_eq!(strings[1], "second string");
+                assert_eq!(strings[2], "");
+                assert_eq!(strings[5], "");
+            }
+            _ => panic!("Should be Characters!"),
+        }
+    }
+
+    #[test]
+    fn read_bintable_logical_and_bit_columns() {
+        let fits = Fits::open("test/testprog.fit").unwrap();
+        let table = fits[4].read_bintable().unwrap();
+        match table.column(1) {
+            Some(ColumnData::Logical(array)) => {
+                assert_eq!(array.shape, vec![22]);
+                assert_eq!(
+                    &array.data[..6],
+                    &[Some(false), Some(true), None, None, None, Some(false)]
+                );
+            }
+            _ => panic!("Should be Logical!"),
+        }
+        match table.column_by_name("Xvalue") {
+            Some(ColumnData::Bits(array)) => {
+                assert_eq!(array.shape, vec![16, 22]);
+                // 0x4c70
+                assert_eq!(
+                    &array.data[..16],
+                    &[
+                        false, true, false, false, true, true, false, false, false, true, true,
+                        true, false, false, false, false,
+                    ]
+                );
+            }
+            _ => panic!("Should be Bits!"),
+        }
+    }
+
+    #[test]
+    fn read_bintable_integer_columns_with_null() {
+        let fits = Fits::open("test/testprog.fit").unwrap();
+        let table = fits[4].read_bintable().unwrap();
+        match table.column_by_name("Bvalue") {
+            Some(ColumnData::Bytes(array)) => assert_eq!(
+                &array.data[..13],
+                &[
+                    Some(1),
+                    Some(2),
+                    Some(0),
+                    Some(0),
+                    Some(0),
+                    Some(3),
+                    Some(0),
+                    Some(5),
+                    Some(0),
+                    Some(9),
+                    Some(0),
+                    None,
+                    Some(12),
+                ]
+            ),
+            _ => panic!("Should be Bytes!"),
+        }
+        match table.column_by_name("Ivalue") {
+            Some(ColumnData::IntegersI16(array)) => assert_eq!(
+                &array.data[5..13],
+                &[
+                    Some(3),
+                    Some(-4),
+                    Some(5),
+                    Some(-6),
+                    Some(9),
+                    Some(-10),
+                    None,
+                    Some(12)
+                ]
+            ),
+            _ => panic!("Should be IntegersI16!"),
+        }
+    }
+
+    #[test]
+    fn read_bintable_floating_point_columns() {
+        let fits = Fits::open("test/testprog.fit").unwrap();
+        let table = fits[4].read_bintable().unwrap();
+        match table.column_by_name("Evalue") {
+            Some(ColumnData::FloatingPoint32(array)) => {
+                assert_eq!(&array.data[..2], &[1.0, 2.0]);
+                assert_eq!(array.data[6], -4.0);
+                assert!(array.data[11].is_nan());
+            }
+            _ => panic!("Should be FloatingPoint32!"),
+        }
+        match table.column_by_name("Dvalue") {
+            Some(ColumnData::FloatingPoint64(array)) => {
+                assert_eq!(&array.data[..2], &[1.0, 2.0]);
+            }
+            _ => panic!("Should be FloatingPoint64!"),
+        }
+        match table.column_by_name("Cvalue") {
+            Some(ColumnData::ComplexFloatingPoint32(array)) => {
+                assert_eq!(&array.data[..2], &[(1.0, -2.0), (3.0, -4.0)]);
+            }
+            _ => panic!("Should be ComplexFloatingPoint32!"),
+        }
+        match table.column_by_name("Mvalue") {
+            Some(ColumnData::ComplexFloatingPoint64(array)) => {
+                assert_eq!(array.data[9], (17.0, -18.0));
+            }
+            _ => panic!("Should be ComplexFloatingPoint64!"),
+        }
+    }
+
+    #[test]
+    fn read_bintable_unknown_column() {
+        let fits = Fits::open("test/testprog.fit").unwrap();
+        let table = fits[4].read_bintable().unwrap();
+        assert!(table.column(10).is_none());
+        assert!(table.column_by_name("FOOBAR").is_none());
+    }
+
+    #[test]
+    fn read_bintable_variable_length_columns() {
+        let fits = Fits::open("test/testprog.fit").unwrap();
+        let table = fits[5].read_bintable().unwrap();
+        assert_eq!(table.row_count(), 20);
+        assert!(table.columns()[0].is_variable_length());
+        assert_eq!(table.columns()[6].data_type(), ColumnType::FloatingPoint32);
+        match table.column_by_name("Avalue") {
+            Some(ColumnData::VariableLength(rows)) => {
+                assert_eq!(rows.len(), 20);
+                match rows[3] {
+                    ColumnData::Characters(ref strings) => assert_eq!(strings, &["abcd"]),
+                    _ => panic!("Should be Characters!"),
+                }
+            }
+            _ => panic!("Should be VariableLength!"),
+        }
+        match table.column_by_name("Lvalue") {
+            Some(ColumnData::VariableLength(rows)) => match rows[3] {
+                ColumnData::Logical(ref array) => {
+                    assert_eq!(array.data, vec![Some(false), Some(true), None, Some(false)])
+                }
+                _ => panic!("Should be Logical!"),
+            },
+            _ => panic!("Should be VariableLength!"),
+        }
+        match table.column_by_name("Jvalue") {
+            Some(ColumnData::VariableLength(rows)) => match rows[4] {
+                ColumnData::IntegersI32(ref array) => {
+                    assert_eq!(array.shape, vec![5]);
+                    assert_eq!(array.data, vec![Some(1), Some(2), Some(3), None, Some(5)]);
+                }
+                _ => panic!("Should be IntegersI32!"),
+            },
+            _ => panic!("Should be VariableLength!"),
+        }
+        match table.column_by_name("Evalue") {
+            Some(ColumnData::VariableLength(rows)) => match rows[19] {
+                ColumnData::FloatingPoint32(ref array) => {
+                    assert_eq!(array.data.len(), 20);
+                    assert_eq!(&array.data[..3], &[1.0, 2.0, 3.0]);
+                }
+                _ => panic!("Should be FloatingPoint32!"),
+            },
+            _ => panic!("Should be VariableLength!"),
+        }
+        match table.column_by_name("Mvalue") {
+            Some(ColumnData::VariableLength(rows)) => match rows[0] {
+                ColumnData::ComplexFloatingPoint64(ref array) => assert!(array.data.is_empty()),
+                _ => panic!("Should be ComplexFloatingPoint64!"),
+            },
+            _ => panic!("Should be VariableLength!"),
+        }
+    }
+
+    #[test]
+    fn parse_array_descriptor_q() {
+        let column = Column::new(None, "1QJ(3)", 0, None).unwrap();
+        assert!(column.is_variable_length());
+        assert_eq!(column.data_type(), ColumnType::Integer32);
+        assert_eq!(column.width(), Some(16));
+        let field = [0, 0, 0, 0, 0, 0, 0, 3, 0, 0, 0, 0, 0, 0, 1, 0];
+        assert_eq!(ArrayDescriptor::Q.read(&field), Some((3, 256)));
+        let negative = [0xff, 0xff, 0xff, 0xff, 0, 0, 0, 0];
+        assert_eq!(ArrayDescriptor::P.read(&negative), None);
+    }
+
+    #[test]
+    fn read_corrupt_array_descriptors() {
+        let table = |descriptors: &[(i64, i64)]| {
+            let mut data = Vec::new();
+            for &(count, offset) in descriptors {
+                data.extend_from_slice(&count.to_be_bytes());
+                data.extend_from_slice(&offset.to_be_bytes());
+            }
+            BinTable {
+                hdu: Some(3),
+                columns: vec![Column::new(Some(String::from("Q")), "1QJ", 0, None).unwrap()],
+                row_length: 16,
+                row_count: descriptors.len(),
+                data,
+                heap: vec![0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0, 2],
+            }
+        };
+        match table(&[(2, 4)]).try_column(0) {
+            Ok(Some(ColumnData::VariableLength(rows))) => match rows[0] {
+                ColumnData::IntegersI32(ref array) => {
+                    assert_eq!(array.data, vec![Some(1), Some(2)])
+                }
+                _ => panic!("Should be IntegersI32!"),
+            },
+            _ => panic!("Should be VariableLength!"),
+        }
+        for &descriptor in &[(2, 8), (-1, 0), (1, -4), (i64::MAX, 8), (1, i64::MAX)] {
+            match table(&[(0, 0), descriptor]).try_column_by_name("Q") {
+                Err(FitsError::InvalidDescriptor { hdu, column, row }) => {
+                    assert_eq!((hdu, column, row), (Some(3), 0, 1))
+                }
+                _ => panic!("Should be InvalidDescriptor!"),
+            }
+        }
+        let error = table(&[(-1, 0)]).try_column(0).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Invalid variable length array descriptor in row 0 of column 0 in HDU 3"
+        );
+        assert!(table(&[]).try_column(1).unwrap().is_none());
+    }
+
+    #[test]
+    fn read_ascii_table_columns() {
+        let fits = Fits::open("test/testprog.fit").unwrap();
+        let table = fits["Test-ASCII"].read_ascii_table().unwrap();
+        assert_eq!(table.row_count(), 12);
+        let columns = table.columns();
+        assert_eq!(columns.len(), 5);
+        assert_eq!(columns[0].name(), Some("Name"));
+        assert_eq!(columns[0].data_type(), AsciiColumnType::Character);
+        assert_eq!(columns[0].width(), 15);
+        assert_eq!(columns[1].data_type(), AsciiColumnType::Integer);
+        assert_eq!(columns[4].name(), Some("INSERT_COL"));
+        assert_eq!(columns[4].data_type(), AsciiColumnType::FloatingPoint);
+        assert!(fits[4].read_ascii_table().is_none());
+    }
+
+    #[test]
+    fn read_ascii_table_character_column() {
+        let fits = Fits::open("test/testprog.fit").unwrap();
+        let table = fits["Test-ASCII"].read_ascii_table().unwrap();
+        match table.column_by_name("Name") {
+            Some(AsciiColumnData::Characters(strings)) => {
+                assert_eq!(strings[0], Some(String::from("first string")));
+                assert_eq!(strings[2], Some(String::new()));
+                assert_eq!(strings[6], None);
+            }
+            _ => panic!("Should be Characters!"),
+        }
+    }
+
+    #[test]
+    fn read_ascii_table_numeric_columns() {
+        let fits = Fits::open("test/testprog.fit").unwrap();
+        let table = fits["Test-ASCII"].read_ascii_table().unwrap();
+        match table.column_by_name("Ivalue") {
+            Some(AsciiColumnData::Integers(array)) => {
+                assert_eq!(array.shape, vec![12]);
+                assert_eq!(
+                    &array.data[..7],
+                    &[Some(1), Some(2), None, None, None, Some(3), Some(4)]
+                );
+                assert_eq!(array.data[11], None);
+            }
+            _ => panic!("Should be Integers!"),
+        }
+        match table.column(2) {
+            Some(AsciiColumnData::FloatingPoint(array)) => {
+                assert_eq!(&array.data[..3], &[Some(1.0), Some(2.0), None]);
+                assert_eq!(array.data[10], Some(10.0));
+                assert_eq!(array.data[11], None);
+            }
+            _ => panic!("Should be FloatingPoint!"),
+        }
+        match table.column_by_name("Dvalue") {
+            Some(AsciiColumnData::FloatingPoint(array)) => {
+                assert_eq!(array.data[1], Some(2.0));
+                assert_eq!(array.data[11], None);
+            }
+            _ => panic!("Should be FloatingPoint!"),
+        }
+    }
+
+    #[test]
+    fn parse_ascii_floating_point() {
+        let column = AsciiColumn::new(None, "D12.3", 1, None).unwrap();
+        assert_eq!(column.parse_floating_point("1.5D+03"), Some(1500.0));
+        assert_eq!(column.parse_floating_point("-12345"), Some(-12.345));
+        assert_eq!(column.parse_floating_point("12345E2"), Some(1234.5));
+        assert_eq!(column.parse_floating_point("null"), None);
+    }
+}